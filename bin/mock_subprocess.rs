@@ -8,7 +8,9 @@ use std::fs::{
 use std::{
     env::{
         args_os,
+        current_dir,
         current_exe,
+        vars_os,
     },
     ffi::OsString,
     fs::File,
@@ -17,12 +19,18 @@ use std::{
         Path,
         PathBuf,
     },
-    process::exit,
+    process::{
+        exit,
+        Command,
+    },
+    thread::sleep,
+    time::Duration,
 };
 
 #[cfg(target_os = "macos")]
 const PROC_PIDLISTFDS: libc::c_int = 1;
 
+#[cfg(target_os = "macos")]
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 struct proc_fdinfo {
@@ -63,6 +71,42 @@ fn record_our_args<P: AsRef<Path>, A: IntoIterator<Item = OsString>>(
     }
 }
 
+fn record_our_env<P: AsRef<Path>>(path: P) {
+    let mut f = File::create(
+        [path.as_ref(), Path::new("env")].iter().collect::<PathBuf>(),
+    )
+    .unwrap();
+    for (key, value) in vars_os() {
+        let _ = writeln!(
+            &mut f,
+            "{}={}",
+            key.to_string_lossy(),
+            value.to_string_lossy()
+        );
+    }
+}
+
+fn record_our_cwd<P: AsRef<Path>>(path: P) {
+    let mut f = File::create(
+        [path.as_ref(), Path::new("cwd")].iter().collect::<PathBuf>(),
+    )
+    .unwrap();
+    let _ = write!(&mut f, "{}", current_dir().unwrap().to_string_lossy());
+}
+
+#[cfg(target_os = "windows")]
+fn record_our_ids<P: AsRef<Path>>(_path: P) {}
+
+#[cfg(unix)]
+fn record_our_ids<P: AsRef<Path>>(path: P) {
+    let mut f = File::create(
+        [path.as_ref(), Path::new("ids")].iter().collect::<PathBuf>(),
+    )
+    .unwrap();
+    let _ = writeln!(&mut f, "{}", unsafe { libc::getuid() });
+    let _ = writeln!(&mut f, "{}", unsafe { libc::getgid() });
+}
+
 #[cfg(target_os = "windows")]
 fn record_our_handles<P: AsRef<Path>>(_path: P) {}
 
@@ -86,7 +130,7 @@ fn record_our_handles<P: AsRef<Path>>(path: P) {
     let report = fds
         .into_iter()
         .filter_map(|fd| {
-            read_link(format!("/proc/self/fd/{}", fd))
+            read_link(format!("/proc/self/fd/{fd}"))
                 .ok()
                 .filter(|link| fd > 2 || *link != dev_null)
                 .map(|link| format!("{}: {}", fd, link.to_string_lossy()))
@@ -97,7 +141,7 @@ fn record_our_handles<P: AsRef<Path>>(path: P) {
     )
     .unwrap();
     for line in report {
-        let _ = writeln!(&mut f, "{}", line);
+        let _ = writeln!(&mut f, "{line}");
     }
 }
 
@@ -144,7 +188,7 @@ fn record_our_handles<P: AsRef<Path>>(path: P) {
     )
     .unwrap();
     for line in report {
-        let _ = writeln!(&mut f, "{}", line);
+        let _ = writeln!(&mut f, "{line}");
     }
 }
 
@@ -155,9 +199,44 @@ fn main() {
             let path = PathBuf::from(args.get(1).unwrap());
             record_our_pid(&path);
             record_our_args(&path, args);
+            record_our_env(&path);
+            record_our_cwd(&path);
+            record_our_ids(&path);
             record_our_handles(&path);
             exit(0)
         },
+        Some(command) if command == "sleep" => {
+            // Give up eventually, so that a test which fails to clean up
+            // after itself does not leave this around for long.
+            sleep(Duration::from_secs(30));
+        },
+        #[cfg(unix)]
+        Some(command) if command == "ignore-terminate" => {
+            unsafe { libc::signal(libc::SIGTERM, libc::SIG_IGN) };
+            println!("ready");
+            sleep(Duration::from_secs(30));
+        },
+        Some(command) if command == "parent" => {
+            let mut child = Command::new(current_exe().unwrap())
+                .arg("sleep")
+                .spawn()
+                .unwrap();
+            println!("{}", child.id());
+            child.wait().unwrap();
+        },
+        Some(command) if command == "sleep-with-child" => {
+            let mut child = Command::new(current_exe().unwrap())
+                .arg("sleep")
+                .spawn()
+                .unwrap();
+            println!("{}", child.id());
+            sleep(Duration::from_secs(30));
+            child.wait().unwrap();
+        },
+        Some(command) if command == "print" => {
+            println!("output");
+            eprintln!("error output");
+        },
         Some(command) if command == "where" => {
             print!("{}", current_exe().unwrap().to_string_lossy());
         },
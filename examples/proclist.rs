@@ -3,9 +3,13 @@ use rhymuproc::list_processes;
 fn main() {
     for process in list_processes() {
         println!(
-            "{}: {}; {:?}",
+            "{}: {} ({}); {:?}",
             process.id,
-            process.image.to_string_lossy(),
+            process.name.to_string_lossy(),
+            process.image.as_ref().map_or_else(
+                || "<unknown image>".into(),
+                |image| image.to_string_lossy()
+            ),
             process.tcp_server_ports
         );
     }
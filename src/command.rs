@@ -0,0 +1,428 @@
+use crate::{
+    start_detached_internal,
+    Error,
+    ProcessHandle,
+};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::{
+    env::vars_os,
+    ffi::{
+        OsStr,
+        OsString,
+    },
+    fs::{
+        File,
+        OpenOptions,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// This is a builder for starting a new detached process, as with
+/// [`start_detached`], with more control over how the process is set up.
+///
+/// ```rust,no_run
+/// # use rhymuproc::DetachedCommand;
+/// let pid = DetachedCommand::new("/usr/sbin/mydaemon")
+///     .arg("--foreground")
+///     .current_dir("/")
+///     .stdout("/var/log/mydaemon.log")
+///     .stderr("/var/log/mydaemon.log")
+///     .env_clear()
+///     .env("PATH", "/usr/bin:/bin")
+///     .start()
+///     .unwrap();
+/// ```
+///
+/// [`start_detached`]: fn.start_detached.html
+pub struct DetachedCommand {
+    pub(crate) program: PathBuf,
+    pub(crate) args: Vec<OsString>,
+    pub(crate) current_dir: Option<PathBuf>,
+    stdout: Option<PathBuf>,
+    stderr: Option<PathBuf>,
+    #[cfg(unix)]
+    pub(crate) passed_fds: Vec<(RawFd, RawFd)>,
+    pub(crate) listen_fd_count: usize,
+    #[cfg(unix)]
+    pub(crate) uid: Option<u32>,
+    #[cfg(unix)]
+    pub(crate) gid: Option<u32>,
+    #[cfg(unix)]
+    pub(crate) groups: Option<Vec<u32>>,
+    #[cfg(unix)]
+    pub(crate) init_groups_user: Option<OsString>,
+    env_clear: bool,
+    env_changes: Vec<(OsString, Option<OsString>)>,
+}
+
+impl DetachedCommand {
+    /// Begin setting up a new detached process which will run the primary
+    /// executable at the given `program` path.  By default the process has
+    /// no arguments, inherits the environment and working directory of the
+    /// caller, and has its standard input and output attached to the null
+    /// device.
+    pub fn new<P>(program: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            program: program.as_ref().to_path_buf(),
+            args: Vec::new(),
+            current_dir: None,
+            stdout: None,
+            stderr: None,
+            #[cfg(unix)]
+            passed_fds: Vec::new(),
+            listen_fd_count: 0,
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            init_groups_user: None,
+            env_clear: false,
+            env_changes: Vec::new(),
+        }
+    }
+
+    /// Add an argument to give the new process on its command line.
+    pub fn arg<S>(
+        &mut self,
+        arg: S,
+    ) -> &mut Self
+    where
+        S: AsRef<OsStr>,
+    {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add several arguments to give the new process on its command line.
+    pub fn args<A, S>(
+        &mut self,
+        args: A,
+    ) -> &mut Self
+    where
+        A: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args.extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an environment variable for the new process, replacing any
+    /// inherited value.
+    pub fn env<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> &mut Self
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.env_changes
+            .push((key.as_ref().to_owned(), Some(value.as_ref().to_owned())));
+        self
+    }
+
+    /// Keep the new process from inheriting the given environment variable,
+    /// or undo setting it with [`env`].
+    ///
+    /// [`env`]: #method.env
+    pub fn env_remove<K>(
+        &mut self,
+        key: K,
+    ) -> &mut Self
+    where
+        K: AsRef<OsStr>,
+    {
+        self.env_changes.push((key.as_ref().to_owned(), None));
+        self
+    }
+
+    /// Keep the new process from inheriting any environment variables, and
+    /// undo setting any with [`env`], so that it has only those set with
+    /// [`env`] afterwards.
+    ///
+    /// [`env`]: #method.env
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env_clear = true;
+        self.env_changes.clear();
+        self
+    }
+
+    /// Set the working directory of the new process.  If the process cannot
+    /// be started in this directory, starting it fails.
+    pub fn current_dir<P>(
+        &mut self,
+        dir: P,
+    ) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Append the standard output of the new process to the file at the
+    /// given `path`, creating it if necessary, rather than discarding it.
+    pub fn stdout<P>(
+        &mut self,
+        path: P,
+    ) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.stdout = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Append the standard error output of the new process to the file at
+    /// the given `path`, creating it if necessary, rather than discarding
+    /// it.  This may be the same file given to [`stdout`].
+    ///
+    /// [`stdout`]: #method.stdout
+    pub fn stderr<P>(
+        &mut self,
+        path: P,
+    ) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.stderr = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Keep the caller's open file descriptor `fd` open in the new process,
+    /// with the same number.  It must stay open until the process is
+    /// started.  Otherwise the new process inherits no file descriptors other
+    /// than its standard input and output.
+    #[cfg(unix)]
+    pub fn pass_fd(
+        &mut self,
+        fd: RawFd,
+    ) -> &mut Self {
+        self.pass_fd_as(fd, fd)
+    }
+
+    /// Keep the caller's open file descriptor `fd` open in the new process,
+    /// with the number `target` instead.  It must stay open until the
+    /// process is started.  Starting the process fails with
+    /// [`Error::PassFd`] if `target` is that of the standard input or output
+    /// (0 to 2), or the same as that of another passed descriptor, including
+    /// one passed with [`listen_fd`].
+    ///
+    /// [`Error::PassFd`]: enum.Error.html#variant.PassFd
+    /// [`listen_fd`]: #method.listen_fd
+    #[cfg(unix)]
+    pub fn pass_fd_as(
+        &mut self,
+        fd: RawFd,
+        target: RawFd,
+    ) -> &mut Self {
+        self.passed_fds.push((fd, target));
+        self
+    }
+
+    /// Pass the caller's open file descriptor `fd`, such as a listening
+    /// socket, to the new process in the style of systemd socket activation.
+    /// Descriptors passed this way are given the numbers from 3 onward, in
+    /// the order given, and the new process has the `LISTEN_FDS` environment
+    /// variable set to their count and `LISTEN_PID` set to its own
+    /// identifier.  The descriptor must stay open until the process is
+    /// started.
+    #[cfg(unix)]
+    pub fn listen_fd(
+        &mut self,
+        fd: RawFd,
+    ) -> &mut Self {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_possible_wrap)]
+        let target = LISTEN_FDS_START + self.listen_fd_count as RawFd;
+        self.listen_fd_count += 1;
+        self.pass_fd_as(fd, target)
+    }
+
+    /// Run the new process as the user with the given `uid` (user
+    /// identifier).  Setting this usually requires the caller to have
+    /// superuser privileges.  Unless [`groups`] or [`init_groups`] is also
+    /// used, the new process has no supplementary groups if this is not the
+    /// effective user of the caller.  Unless [`gid`] or [`init_groups`] is
+    /// also used, its primary group stays that of the caller.
+    ///
+    /// [`gid`]: #method.gid
+    /// [`groups`]: #method.groups
+    /// [`init_groups`]: #method.init_groups
+    #[cfg(unix)]
+    pub fn uid(
+        &mut self,
+        uid: u32,
+    ) -> &mut Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Run the new process with the given `gid` (group identifier) as its
+    /// primary group.
+    #[cfg(unix)]
+    pub fn gid(
+        &mut self,
+        gid: u32,
+    ) -> &mut Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Give the new process the given supplementary groups in place of those
+    /// of the caller.  Unless this or [`init_groups`] is used, the new
+    /// process keeps the supplementary groups of the caller, unless it is
+    /// run as another user with [`uid`], in which case it has none.
+    ///
+    /// [`init_groups`]: #method.init_groups
+    /// [`uid`]: #method.uid
+    #[cfg(unix)]
+    pub fn groups<G>(
+        &mut self,
+        groups: G,
+    ) -> &mut Self
+    where
+        G: IntoIterator<Item = u32>,
+    {
+        self.groups = Some(groups.into_iter().collect());
+        self
+    }
+
+    /// Give the new process the supplementary groups of the named `user`,
+    /// along with its primary group, in the manner of `initgroups`.  These
+    /// are added to any given with [`groups`].  Unless [`gid`] is used, the
+    /// primary group of the user also becomes that of the new process.
+    /// Starting the process fails with [`Error::SetGroups`] if there is no
+    /// such user.
+    ///
+    /// [`Error::SetGroups`]: enum.Error.html#variant.SetGroups
+    /// [`gid`]: #method.gid
+    ///
+    /// [`groups`]: #method.groups
+    #[cfg(unix)]
+    pub fn init_groups<S>(
+        &mut self,
+        user: S,
+    ) -> &mut Self
+    where
+        S: AsRef<OsStr>,
+    {
+        self.init_groups_user = Some(user.as_ref().to_owned());
+        self
+    }
+
+    // This opens the files to give the new process as its standard input,
+    // output, and error output, in that order.
+    pub(crate) fn open_stdio(&self) -> Result<[File; 3], Error> {
+        let open_output = |path: &Option<PathBuf>| match path {
+            Some(path) => {
+                OpenOptions::new().append(true).create(true).open(path)
+            },
+            None => OpenOptions::new().write(true).open(NULL_DEVICE),
+        };
+        let stdin = File::open(NULL_DEVICE).map_err(Error::Stdio)?;
+        let stdout = open_output(&self.stdout).map_err(Error::Stdio)?;
+        let stderr = open_output(&self.stderr).map_err(Error::Stdio)?;
+        Ok([stdin, stdout, stderr])
+    }
+
+    // This returns `None` if the new process should simply inherit the
+    // environment of the caller.  `LISTEN_PID` is left for the caller to
+    // add, since only the new process knows its value.
+    pub(crate) fn environment(&self) -> Option<Vec<(OsString, OsString)>> {
+        if !self.env_clear
+            && self.env_changes.is_empty()
+            && self.listen_fd_count == 0
+        {
+            return None;
+        }
+        let mut environment = if self.env_clear {
+            Vec::new()
+        } else {
+            vars_os().collect::<Vec<_>>()
+        };
+        for (key, value) in &self.env_changes {
+            environment.retain(|(other_key, _)| !same_env_key(key, other_key));
+            if let Some(value) = value {
+                environment.push((key.clone(), value.clone()));
+            }
+        }
+        if self.listen_fd_count > 0 {
+            environment.retain(|(key, _)| {
+                !["LISTEN_FDS", "LISTEN_PID", "LISTEN_FDNAMES"]
+                    .iter()
+                    .any(|listen_key| same_env_key(key, OsStr::new(listen_key)))
+            });
+            environment.push((
+                OsString::from("LISTEN_FDS"),
+                OsString::from(self.listen_fd_count.to_string()),
+            ));
+        }
+        Some(environment)
+    }
+
+    /// Start the new process, returning its identifier.
+    ///
+    /// # Errors
+    ///
+    /// The same errors are returned as by [`try_start_detached`].
+    ///
+    /// [`try_start_detached`]: fn.try_start_detached.html
+    pub fn start(&self) -> Result<usize, Error> {
+        self.start_with_handle().map(|handle| handle.pid())
+    }
+
+    /// Start the new process, returning a [`ProcessHandle`] referring to
+    /// it, as with [`start_detached_with_handle`].
+    ///
+    /// # Errors
+    ///
+    /// The same errors are returned as by [`try_start_detached`].
+    ///
+    /// [`ProcessHandle`]: struct.ProcessHandle.html
+    /// [`start_detached_with_handle`]: fn.start_detached_with_handle.html
+    /// [`try_start_detached`]: fn.try_start_detached.html
+    pub fn start_with_handle(&self) -> Result<ProcessHandle, Error> {
+        start_detached_internal(self).map(ProcessHandle::new)
+    }
+}
+
+// This is the first file descriptor number used for systemd socket
+// activation.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+#[cfg(target_os = "windows")]
+const NULL_DEVICE: &str = "NUL";
+
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+
+// Environment variable names are case-insensitive on Windows.
+#[cfg(target_os = "windows")]
+fn same_env_key(
+    key: &OsStr,
+    other_key: &OsStr,
+) -> bool {
+    key.to_string_lossy().to_uppercase()
+        == other_key.to_string_lossy().to_uppercase()
+}
+
+#[cfg(unix)]
+fn same_env_key(
+    key: &OsStr,
+    other_key: &OsStr,
+) -> bool {
+    key == other_key
+}
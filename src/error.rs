@@ -0,0 +1,172 @@
+use crate::Signal;
+use std::{
+    fmt,
+    io,
+};
+
+/// This is the type of error returned by the fallible functions of this
+/// crate.  Errors reported by the operating system carry the underlying
+/// error code (`errno` on UNIX, `GetLastError` on Windows), which can be
+/// retrieved with [`std::io::Error::raw_os_error`].
+#[derive(Debug)]
+pub enum Error {
+    /// This indicates that the pipe used to hear back from the processes
+    /// forked to start a detached process could not be created.
+    CreatePipe(io::Error),
+
+    /// This indicates that a new process could not be forked, either by the
+    /// caller or by the intermediate process which starts the detached
+    /// process in its own session.
+    Fork(io::Error),
+
+    /// This indicates that the intermediate process forked to start a
+    /// detached process terminated abnormally without reporting the
+    /// identifier of the detached process.
+    IntermediateProcess,
+
+    /// This indicates that a path, argument, environment variable, or user
+    /// name given for the detached process contains a null character, which
+    /// the operating system cannot accept.
+    InvalidInput,
+
+    /// This indicates that a file to give the detached process as its
+    /// standard input or output could not be opened or passed to it.
+    Stdio(io::Error),
+
+    /// This indicates that a file descriptor to pass to the detached process
+    /// could not be duplicated for it, such as when it is not open.
+    PassFd(io::Error),
+
+    /// This indicates that the detached process was forked but could not
+    /// set its supplementary groups.
+    SetGroups(io::Error),
+
+    /// This indicates that the detached process was forked but could not
+    /// set its group identifier.
+    SetGid(io::Error),
+
+    /// This indicates that the detached process was forked but could not
+    /// set its user identifier.
+    SetUid(io::Error),
+
+    /// This indicates that the detached process was forked but its
+    /// executable could not be run, such as when the executable does not
+    /// exist or permission to run it is denied.
+    Exec(io::Error),
+
+    /// This indicates that the detached process was forked but could not
+    /// change to the working directory requested for it.
+    CurrentDir(io::Error),
+
+    /// This indicates that the operating system refused to create a new
+    /// process.
+    CreateProcess(io::Error),
+
+    /// This indicates that there is no process with the given identifier.
+    NoSuchProcess,
+
+    /// This indicates that the caller does not have permission to act on
+    /// the process with the given identifier.
+    PermissionDenied,
+
+    /// This indicates that the process could not be killed or sent a signal
+    /// for some other reason.
+    Kill(io::Error),
+
+    /// This indicates that a handle to the process could not be opened for
+    /// some other reason, such as the caller having run out of file
+    /// descriptors or handles.
+    OpenProcess(io::Error),
+
+    /// This indicates that the given signal cannot be sent on this operating
+    /// system.
+    UnsupportedSignal(Signal),
+
+    /// This indicates that the process with the given identifier is not the
+    /// one expected, because that one exited and another process has since
+    /// been given the same identifier.
+    DifferentProcess,
+}
+
+impl fmt::Display for Error {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Error::CreatePipe(error) => {
+                write!(f, "unable to create pipe: {error}")
+            },
+            Error::Fork(error) => write!(f, "unable to fork process: {error}"),
+            Error::IntermediateProcess => write!(
+                f,
+                "intermediate process terminated without reporting the \
+                 detached process"
+            ),
+            Error::InvalidInput => {
+                write!(f, "input for the process contains a null character")
+            },
+            Error::Stdio(error) => {
+                write!(f, "unable to set up standard input or output: {error}")
+            },
+            Error::PassFd(error) => {
+                write!(f, "unable to pass file descriptor: {error}")
+            },
+            Error::SetGroups(error) => {
+                write!(f, "unable to set supplementary groups: {error}")
+            },
+            Error::SetGid(error) => {
+                write!(f, "unable to set group identifier: {error}")
+            },
+            Error::SetUid(error) => {
+                write!(f, "unable to set user identifier: {error}")
+            },
+            Error::Exec(error) => {
+                write!(f, "unable to execute program: {error}")
+            },
+            Error::CurrentDir(error) => {
+                write!(f, "unable to change working directory: {error}")
+            },
+            Error::CreateProcess(error) => {
+                write!(f, "unable to create process: {error}")
+            },
+            Error::NoSuchProcess => write!(f, "no such process"),
+            Error::PermissionDenied => write!(f, "permission denied"),
+            Error::Kill(error) => write!(f, "unable to kill process: {error}"),
+            Error::OpenProcess(error) => {
+                write!(f, "unable to open process: {error}")
+            },
+            Error::UnsupportedSignal(signal) => {
+                write!(f, "signal {signal:?} is not supported")
+            },
+            Error::DifferentProcess => {
+                write!(f, "process identifier refers to a different process")
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CreatePipe(error)
+            | Error::Fork(error)
+            | Error::Stdio(error)
+            | Error::PassFd(error)
+            | Error::SetGroups(error)
+            | Error::SetGid(error)
+            | Error::SetUid(error)
+            | Error::Exec(error)
+            | Error::CurrentDir(error)
+            | Error::CreateProcess(error)
+            | Error::Kill(error)
+            | Error::OpenProcess(error) => Some(error),
+            Error::IntermediateProcess
+            | Error::InvalidInput
+            | Error::NoSuchProcess
+            | Error::PermissionDenied
+            | Error::UnsupportedSignal(_)
+            | Error::DifferentProcess => None,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    Error,
+    ProcessHandleInternal,
+    Signal,
+};
+use std::time::Duration;
+
+/// This refers to one particular process, so that it can be signalled and
+/// waited on without the risk of acting on a different process which was
+/// later given the same identifier.
+///
+/// On Linux this holds a process file descriptor, where the kernel supports
+/// them (Linux 5.3 or newer), and on Windows a process handle.  Elsewhere, or
+/// on older Linux kernels, only the process identifier is held, which does
+/// not guard against it being reused.
+///
+/// ```rust
+/// # use rhymuproc::ProcessHandle;
+/// let handle = ProcessHandle::open(std::process::id() as usize).unwrap();
+/// assert_eq!(std::process::id() as usize, handle.pid());
+/// ```
+pub struct ProcessHandle {
+    internal: ProcessHandleInternal,
+}
+
+impl ProcessHandle {
+    pub(crate) fn new(internal: ProcessHandleInternal) -> Self {
+        Self {
+            internal,
+        }
+    }
+
+    /// Open a handle to the process with the given `pid` (process
+    /// identifier).
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NoSuchProcess`] is returned if there is no process with the
+    /// given identifier, and [`Error::PermissionDenied`] if the caller is
+    /// not allowed to open it.  Any other failure to open it is reported as
+    /// [`Error::OpenProcess`].
+    ///
+    /// [`Error::NoSuchProcess`]: enum.Error.html#variant.NoSuchProcess
+    /// [`Error::PermissionDenied`]: enum.Error.html#variant.PermissionDenied
+    /// [`Error::OpenProcess`]: enum.Error.html#variant.OpenProcess
+    pub fn open(pid: usize) -> Result<Self, Error> {
+        ProcessHandleInternal::open(pid).map(Self::new)
+    }
+
+    /// Return the identifier of the process.
+    #[must_use]
+    pub fn pid(&self) -> usize {
+        self.internal.pid()
+    }
+
+    /// Send the given `signal` to the process.
+    ///
+    /// # Errors
+    ///
+    /// The same errors are returned as by [`send_signal`].  In particular,
+    /// [`Error::NoSuchProcess`] is returned if the process has exited.
+    ///
+    /// [`send_signal`]: fn.send_signal.html
+    /// [`Error::NoSuchProcess`]: enum.Error.html#variant.NoSuchProcess
+    pub fn send_signal(
+        &self,
+        signal: Signal,
+    ) -> Result<(), Error> {
+        self.internal.send_signal(signal)
+    }
+
+    /// Terminate the process.
+    ///
+    /// # Errors
+    ///
+    /// The same errors are returned as by [`try_kill`].
+    ///
+    /// [`try_kill`]: fn.try_kill.html
+    pub fn kill(&self) -> Result<(), Error> {
+        self.send_signal(Signal::Kill)
+    }
+
+    /// Wait up to `timeout` for the process to exit, returning whether or not
+    /// it did.
+    #[must_use]
+    pub fn wait_for_exit(
+        &self,
+        timeout: Duration,
+    ) -> bool {
+        self.internal.wait_for_exit(timeout)
+    }
+}
@@ -3,37 +3,106 @@
 //!
 //! * [`list_processes`] &ndash; poll the operating system for a list of the
 //!   current running processes, along with paths to the primary image
-//!   (executable file) of each process and the set of TCP server ports
-//!   currently bound by each process
+//!   (executable file) of each process and the sets of TCP server addresses and
+//!   ports currently bound by each process
+//! * [`process_info`] &ndash; poll the operating system for the same
+//!   information about a single process, given its identifier
+//! * [`process_listening_on`] &ndash; find the processes listening for TCP
+//!   connections on a given port
+//! * [`is_running`] &ndash; cheaply check whether a process is running, and
+//!   [`is_zombie`] whether it has exited but not yet been reaped
 //! * [`start_detached`] &ndash; start a new process that inherits no file
-//!   handles and operates in its own session
-//! * [`kill`] &ndash; terminate another process
+//!   handles and operates in its own session, with [`try_start_detached`]
+//!   reporting why it could not be started, and [`DetachedCommand`] giving more
+//!   control over how it is set up, such as its environment, working directory,
+//!   log files, any file descriptors passed to it and the user it runs as
+//! * [`kill`] &ndash; terminate another process, with [`try_kill`] reporting
+//!   whether it was killed, and [`kill_verified`] refusing to kill a process
+//!   which has replaced it
+//! * [`send_signal`] &ndash; send another process a [`Signal`], such as one
+//!   asking it to shut down cleanly
+//! * [`terminate`] &ndash; ask another process to shut down cleanly, killing it
+//!   if it does not exit within a grace period
+//! * [`kill_tree`] &ndash; kill another process along with all of its
+//!   descendants
+//! * [`wait_for_exit`] &ndash; wait, with a timeout, for another process to
+//!   exit, even one which is not a child of the caller
+//!
+//! A [`ProcessTree`] can be made from the list of processes in order to find
+//! their children, ancestors and descendants.  A [`ProcessHandle`] refers to
+//! one particular process, for signalling and waiting on it without acting
+//! on a different process which was later given the same identifier.  A
+//! [`ProcessId`] combines the identifier of a process with its start time,
+//! so that [`is_same_process`] can tell whether it has been replaced.
 //!
 //! [`list_processes`]: fn.list_processes.html
+//! [`process_info`]: fn.process_info.html
+//! [`process_listening_on`]: fn.process_listening_on.html
+//! [`is_running`]: fn.is_running.html
+//! [`is_zombie`]: fn.is_zombie.html
 //! [`start_detached`]: fn.start_detached.html
+//! [`try_start_detached`]: fn.try_start_detached.html
+//! [`DetachedCommand`]: struct.DetachedCommand.html
 //! [`kill`]: fn.kill.html
+//! [`try_kill`]: fn.try_kill.html
+//! [`kill_verified`]: fn.kill_verified.html
+//! [`send_signal`]: fn.send_signal.html
+//! [`Signal`]: enum.Signal.html
+//! [`terminate`]: fn.terminate.html
+//! [`kill_tree`]: fn.kill_tree.html
+//! [`wait_for_exit`]: fn.wait_for_exit.html
+//! [`ProcessTree`]: struct.ProcessTree.html
+//! [`ProcessHandle`]: struct.ProcessHandle.html
+//! [`ProcessId`]: struct.ProcessId.html
+//! [`is_same_process`]: fn.is_same_process.html
 
 #![warn(clippy::pedantic)]
 #![warn(missing_docs)]
 
+mod command;
+mod error;
+mod handle;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+mod process_id;
+mod signal;
+mod tree;
 #[cfg(unix)]
 mod unix;
 #[cfg(target_os = "windows")]
 mod windows;
 
+pub use command::DetachedCommand;
+pub use error::Error;
+pub use handle::ProcessHandle;
+pub use process_id::ProcessId;
+pub use signal::Signal;
+pub use tree::ProcessTree;
+
 use std::{
     collections::HashSet,
-    ffi::OsStr,
+    ffi::{
+        OsStr,
+        OsString,
+    },
+    net::SocketAddr,
     path::{
         Path,
         PathBuf,
     },
+    thread::sleep,
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
+// This is how often to check whether a process has exited, when there is no
+// way to be notified of it.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 /// This holds information about one running process managed by the operating
 /// system.
 pub struct ProcessInfo {
@@ -43,35 +112,251 @@ pub struct ProcessInfo {
     /// [`kill`]: fn.kill.html
     pub id: usize,
 
+    /// This is the identifier of the process which started this one, if
+    /// known.  It is `None` for processes with no parent, such as the first
+    /// process started by the operating system.  Note that the parent may
+    /// have exited since, and its identifier reused.
+    pub parent_id: Option<usize>,
+
+    /// This is the time at which the process started, if known.  Together
+    /// with [`id`] it identifies the process uniquely, as returned by
+    /// [`process_id`].  See [`ProcessId::start_time`] for its units.
+    ///
+    /// [`id`]: #structfield.id
+    /// [`process_id`]: #method.process_id
+    /// [`ProcessId::start_time`]: struct.ProcessId.html#method.start_time
+    pub start_time: Option<u64>,
+
+    /// This is the short name of the process as the operating system records
+    /// it, such as the file name of its executable.  It is available even
+    /// when [`image`] is not, though it may be truncated (to 15 bytes on
+    /// Linux, for example).
+    ///
+    /// [`image`]: #structfield.image
+    pub name: OsString,
+
     /// This is the path in the filesystem of the primary image (executable
-    /// file) of the process.
-    pub image: PathBuf,
+    /// file) of the process, if known.  It is `None` for processes which have
+    /// no image, such as kernel threads and zombie processes, and for
+    /// processes whose image cannot be read, such as those belonging to other
+    /// users.
+    pub image: Option<PathBuf>,
+
+    /// These are the command-line arguments of the process, starting with
+    /// the program name as the process was given it.  Arguments are kept
+    /// exactly as given, even if they are not valid UTF-8.  This is empty if
+    /// the arguments could not be read, such as when access to them is
+    /// denied.
+    pub args: Vec<OsString>,
+
+    /// This is the set of local socket addresses on which the process is
+    /// currently listening for TCP connections.  Unlike
+    /// [`tcp_server_ports`], this tells apart a server bound to a loopback or
+    /// specific interface address from one bound to all interfaces.
+    ///
+    /// [`tcp_server_ports`]: #structfield.tcp_server_ports
+    pub tcp_server_addresses: HashSet<SocketAddr>,
 
-    /// This is the set of TCP server ports currently bound by the process.
+    /// This is the set of TCP server ports currently bound by the process,
+    /// whether over IPv4 or IPv6.  It holds the ports of
+    /// [`tcp_server_addresses`].
+    ///
+    /// [`tcp_server_addresses`]: #structfield.tcp_server_addresses
     pub tcp_server_ports: HashSet<u16>,
+
+    /// This is the subset of [`tcp_server_ports`] which the process has bound
+    /// over IPv6.  A port bound over IPv4 only will be found in
+    /// [`tcp_server_ports`] but not here.
+    ///
+    /// [`tcp_server_ports`]: #structfield.tcp_server_ports
+    pub tcp6_server_ports: HashSet<u16>,
+
+    /// This is the list of TCP connections, whether outbound or accepted,
+    /// currently held by the process.  Listening sockets are not included
+    /// here; see [`tcp_server_addresses`] for those.
+    ///
+    /// [`tcp_server_addresses`]: #structfield.tcp_server_addresses
+    pub tcp_connections: Vec<TcpConnection>,
+
+    /// This is the set of UDP ports currently bound by the process, whether
+    /// over IPv4 or IPv6.
+    pub udp_ports: HashSet<u16>,
+
+    /// This is the set of names of UNIX domain sockets on which the process
+    /// is currently listening for connections or, for datagram sockets,
+    /// receiving.  This is always empty on Windows.
+    pub unix_socket_listeners: HashSet<UnixSocketName>,
+}
+
+impl ProcessInfo {
+    /// Return the identifier of the process combined with its start time,
+    /// which stays unique even after the process exits and its identifier
+    /// is reused.  This is `None` if the start time is not known.
+    #[must_use]
+    pub fn process_id(&self) -> Option<ProcessId> {
+        self.start_time.map(|start_time| ProcessId::new(self.id, start_time))
+    }
+}
+
+/// This is the name to which a UNIX domain socket is bound.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UnixSocketName {
+    /// The socket is bound to a path in the filesystem.
+    Path(PathBuf),
+
+    /// The socket is bound to a name in the Linux abstract socket namespace.
+    /// The leading null byte which marks such names is not included.
+    Abstract(OsString),
+}
+
+/// This describes one TCP connection held by a process.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TcpConnection {
+    /// This is the local address and port of the connection.
+    pub local_address: SocketAddr,
+
+    /// This is the address and port of the remote endpoint of the
+    /// connection.
+    pub remote_address: SocketAddr,
+
+    /// This is the state the connection is currently in.
+    pub state: TcpState,
+}
+
+/// This identifies the state of a TCP connection.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TcpState {
+    /// The connection is closed.
+    Closed,
+
+    /// The socket is listening for incoming connections.
+    Listen,
+
+    /// A connection request has been sent, and the socket is waiting for a
+    /// matching connection request.
+    SynSent,
+
+    /// A connection request has been both received and sent, and the socket
+    /// is waiting for the request to be acknowledged.
+    SynReceived,
+
+    /// The connection is open, and data may be exchanged.
+    Established,
+
+    /// The local end has closed the connection, and is waiting for the remote
+    /// end to acknowledge it or close its end as well.
+    FinWait1,
+
+    /// The remote end has acknowledged the local end closing the connection,
+    /// and the local end is waiting for the remote end to close its end.
+    FinWait2,
+
+    /// The remote end has closed the connection, and the local end has yet to
+    /// close its end.
+    CloseWait,
+
+    /// Both ends are closing the connection at the same time.
+    Closing,
+
+    /// The remote end closed first, and the local end is waiting for the
+    /// acknowledgment of closing its own end.
+    LastAck,
+
+    /// The connection is closed, and the local end is waiting to make sure
+    /// the remote end received the final acknowledgment.
+    TimeWait,
+
+    /// The operating system reported a state not covered by the others.
+    Unknown,
+}
+
+// Derive the sets of all TCP server ports and of those bound over IPv6 from
+// the full set of addresses on which a process is listening.
+#[allow(clippy::similar_names)]
+fn tcp_server_ports(
+    tcp_server_addresses: &HashSet<SocketAddr>
+) -> (HashSet<u16>, HashSet<u16>) {
+    let tcp_server_ports =
+        tcp_server_addresses.iter().map(SocketAddr::port).collect();
+    let tcp6_server_ports = tcp_server_addresses
+        .iter()
+        .filter(|address| address.is_ipv6())
+        .map(SocketAddr::port)
+        .collect();
+    (tcp_server_ports, tcp6_server_ports)
 }
 
 #[cfg(target_os = "linux")]
 use linux::close_all_files_except;
 #[cfg(target_os = "linux")]
+use linux::is_zombie_internal;
+#[cfg(target_os = "linux")]
 use linux::list_processes_internal;
+#[cfg(target_os = "linux")]
+use linux::process_info_internal;
+#[cfg(target_os = "linux")]
+use linux::process_listening_on_internal;
+#[cfg(target_os = "linux")]
+use linux::start_time_internal;
+#[cfg(target_os = "linux")]
+use linux::wait_for_exit_internal;
+#[cfg(target_os = "linux")]
+use linux::ProcessHandleInternal;
 
 #[cfg(target_os = "macos")]
 use macos::close_all_files_except;
 #[cfg(target_os = "macos")]
+use macos::is_zombie_internal;
+#[cfg(target_os = "macos")]
 use macos::list_processes_internal;
+#[cfg(target_os = "macos")]
+use macos::process_info_internal;
+#[cfg(target_os = "macos")]
+use macos::process_listening_on_internal;
+#[cfg(target_os = "macos")]
+use macos::start_time_internal;
+#[cfg(target_os = "macos")]
+use macos::wait_for_exit_internal;
+#[cfg(target_os = "macos")]
+use macos::ProcessHandleInternal;
 
 #[cfg(unix)]
-use unix::kill_internal;
+use unix::group_members_internal;
+#[cfg(unix)]
+use unix::is_running_internal;
+#[cfg(unix)]
+use unix::send_signal_internal;
+#[cfg(unix)]
+use unix::signal_error;
+#[cfg(unix)]
+use unix::signal_number;
 #[cfg(unix)]
 use unix::start_detached_internal;
+#[cfg(unix)]
+use unix::to_pid_t;
 
 #[cfg(target_os = "windows")]
-use windows::kill_internal;
+use windows::group_members_internal;
+#[cfg(target_os = "windows")]
+use windows::is_running_internal;
+#[cfg(target_os = "windows")]
+use windows::is_zombie_internal;
 #[cfg(target_os = "windows")]
 use windows::list_processes_internal;
 #[cfg(target_os = "windows")]
+use windows::process_info_internal;
+#[cfg(target_os = "windows")]
+use windows::process_listening_on_internal;
+#[cfg(target_os = "windows")]
+use windows::send_signal_internal;
+#[cfg(target_os = "windows")]
 use windows::start_detached_internal;
+#[cfg(target_os = "windows")]
+use windows::start_time_internal;
+#[cfg(target_os = "windows")]
+use windows::wait_for_exit_internal;
+#[cfg(target_os = "windows")]
+use windows::ProcessHandleInternal;
 
 /// Poll the operating system to return information about all currently running
 /// processes.
@@ -79,10 +364,45 @@ pub fn list_processes() -> impl Iterator<Item = ProcessInfo> {
     list_processes_internal()
 }
 
+/// Poll the operating system to return information about the single process
+/// with the given `pid` (process identifier), or `None` if there is no such
+/// process.  This is cheaper than searching the results of
+/// [`list_processes`] when the process identifier is already known.
+///
+/// [`list_processes`]: fn.list_processes.html
+#[must_use]
+pub fn process_info(pid: usize) -> Option<ProcessInfo> {
+    process_info_internal(pid)
+}
+
+/// Find the processes currently listening for TCP connections on the given
+/// `port`, whether over IPv4 or IPv6.  This is cheaper than searching the
+/// results of [`list_processes`], because only the processes owning a
+/// listener on the port are looked at in full.
+///
+/// More than one process is returned if several share the port, such as
+/// with `SO_REUSEPORT`, or share one listening socket, such as the workers
+/// of a pre-forked server.  The result is empty if nothing is listening on the
+/// port.
+///
+/// [`list_processes`]: fn.list_processes.html
+#[must_use]
+pub fn process_listening_on(port: u16) -> Vec<ProcessInfo> {
+    process_listening_on_internal(port)
+}
+
 /// Start a new process that inherits no file handles and runs in an
 /// independent session.  The caller provides the `path` of the primary
 /// executable to run in the new process, as well as any `args` (arguments)
-/// to provide the new process on its command line.
+/// to provide the new process on its command line.  Use
+/// [`DetachedCommand`] for more control over how the process is set up.
+///
+/// The identifier of the new process is returned, or `0` if it could not be
+/// started.  Use [`try_start_detached`] to find out why a process could not
+/// be started.
+///
+/// [`try_start_detached`]: fn.try_start_detached.html
+/// [`DetachedCommand`]: struct.DetachedCommand.html
 pub fn start_detached<P, A, S>(
     path: P,
     args: A,
@@ -92,12 +412,306 @@ where
     A: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    start_detached_internal(path, args)
+    try_start_detached(path, args).unwrap_or(0)
 }
 
-/// Terminate the process with the given `pid` (process identifier).
+/// Start a new process in the same way as [`start_detached`], returning the
+/// identifier of the new process.
+///
+/// # Errors
+///
+/// An [`Error`] is returned, carrying the error code reported by the
+/// operating system, if the new process could not be started.
+/// [`Error::InvalidInput`] is returned if the path or an argument contains a
+/// null character.
+///
+/// [`start_detached`]: fn.start_detached.html
+/// [`Error`]: enum.Error.html
+/// [`Error::InvalidInput`]: enum.Error.html#variant.InvalidInput
+pub fn try_start_detached<P, A, S>(
+    path: P,
+    args: A,
+) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    A: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    start_detached_with_handle(path, args).map(|handle| handle.pid())
+}
+
+/// Start a new process in the same way as [`start_detached`], returning a
+/// [`ProcessHandle`] referring to the new process.  The handle is opened
+/// before the new process could be reaped, so it cannot refer to any other
+/// process which is later given the same identifier.
+///
+/// # Errors
+///
+/// The same errors are returned as by [`try_start_detached`].
+///
+/// [`start_detached`]: fn.start_detached.html
+/// [`try_start_detached`]: fn.try_start_detached.html
+/// [`ProcessHandle`]: struct.ProcessHandle.html
+pub fn start_detached_with_handle<P, A, S>(
+    path: P,
+    args: A,
+) -> Result<ProcessHandle, Error>
+where
+    P: AsRef<Path>,
+    A: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    DetachedCommand::new(path).args(args).start_with_handle()
+}
+
+/// Terminate the process with the given `pid` (process identifier).  Any
+/// failure is ignored; use [`try_kill`] to find out whether the process was
+/// killed.
+///
+/// [`try_kill`]: fn.try_kill.html
 pub fn kill(pid: usize) {
-    kill_internal(pid)
+    let _ = try_kill(pid);
+}
+
+/// Terminate the process with the given `pid` (process identifier).
+///
+/// # Errors
+///
+/// [`Error::NoSuchProcess`] is returned if there is no process with the
+/// given identifier, and [`Error::PermissionDenied`] if the caller is not
+/// allowed to terminate it.  Any other failure is returned as
+/// [`Error::Kill`], carrying the error code reported by the operating
+/// system.
+///
+/// [`Error::NoSuchProcess`]: enum.Error.html#variant.NoSuchProcess
+/// [`Error::PermissionDenied`]: enum.Error.html#variant.PermissionDenied
+/// [`Error::Kill`]: enum.Error.html#variant.Kill
+pub fn try_kill(pid: usize) -> Result<(), Error> {
+    send_signal(pid, Signal::Kill)
+}
+
+/// Send the given `signal` to the process with the given `pid` (process
+/// identifier).
+///
+/// # Errors
+///
+/// The same errors are returned as by [`try_kill`].  In addition,
+/// [`Error::UnsupportedSignal`] is returned if the signal cannot be sent on
+/// this operating system, such as [`Signal::Hangup`] on Windows.
+///
+/// [`try_kill`]: fn.try_kill.html
+/// [`Error::UnsupportedSignal`]: enum.Error.html#variant.UnsupportedSignal
+/// [`Signal::Hangup`]: enum.Signal.html#variant.Hangup
+pub fn send_signal(
+    pid: usize,
+    signal: Signal,
+) -> Result<(), Error> {
+    send_signal_internal(pid, signal)
+}
+
+// Stopping the processes of a tree before killing any of them keeps them
+// from starting more while the tree is being found.  Windows cannot stop a
+// process, so there the tree is killed from the bottom up instead.
+#[cfg(unix)]
+fn freeze(pid: usize) -> Result<(), Error> {
+    send_signal(pid, Signal::Stop)
+}
+
+#[cfg(target_os = "windows")]
+fn freeze(pid: usize) -> Result<(), Error> {
+    if is_running_internal(pid) {
+        Ok(())
+    } else {
+        Err(Error::NoSuchProcess)
+    }
+}
+
+fn poll_for_exit(
+    pid: usize,
+    timeout: Duration,
+) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if !is_running_internal(pid) {
+            return true;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        sleep(EXIT_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Wait up to `timeout` for the process with the given `pid` (process
+/// identifier) to exit, returning whether or not it did.  This works for
+/// any process, not only children of the caller.  The result is `true`
+/// right away if there is no such process.
+///
+/// On Linux the operating system gives notice of the exit through a
+/// process file descriptor where available, as it does on Windows through
+/// the process handle.  Otherwise the process is checked periodically.
+#[must_use]
+pub fn wait_for_exit(
+    pid: usize,
+    timeout: Duration,
+) -> bool {
+    wait_for_exit_internal(pid, timeout)
+}
+
+/// Determine whether the process with the given `pid` (process identifier)
+/// is running.  This is much cheaper than searching the results of
+/// [`list_processes`].  A process which the caller is not allowed to signal
+/// or open still counts as running, while a zombie process, which has
+/// exited but not yet been reaped by its parent, does not.
+///
+/// [`list_processes`]: fn.list_processes.html
+#[must_use]
+pub fn is_running(pid: usize) -> bool {
+    is_running_internal(pid)
+}
+
+/// Determine whether the process with the given `pid` (process identifier)
+/// is a zombie, which has exited but not yet been reaped by its parent.
+/// This is always `false` on Windows, which has no such processes.
+#[must_use]
+pub fn is_zombie(pid: usize) -> bool {
+    is_zombie_internal(pid)
+}
+
+/// Determine whether the process identified by `id` is still running, rather
+/// than having exited, even if another process has since been given the
+/// same identifier.
+#[must_use]
+pub fn is_same_process(id: ProcessId) -> bool {
+    is_running_internal(id.pid())
+        && start_time_internal(id.pid()) == Some(id.start_time())
+}
+
+/// Terminate the process identified by `id`, but only if it is still the
+/// same process, and not another process which has since been given the
+/// same identifier.
+///
+/// # Errors
+///
+/// [`Error::DifferentProcess`] is returned if the process with the given
+/// identifier is not the same one, in which case it is left alone.
+/// Otherwise the same errors are returned as by [`try_kill`].
+///
+/// [`Error::DifferentProcess`]: enum.Error.html#variant.DifferentProcess
+/// [`try_kill`]: fn.try_kill.html
+pub fn kill_verified(id: ProcessId) -> Result<(), Error> {
+    // The handle keeps the process from being replaced between checking it
+    // and killing it, where the operating system supports that.
+    let handle = ProcessHandle::open(id.pid())?;
+    if start_time_internal(id.pid()) != Some(id.start_time()) {
+        return Err(Error::DifferentProcess);
+    }
+    handle.kill()
+}
+
+/// Kill the process with the given `pid` (process identifier) along with
+/// all of its descendants.  Descendants are found through parent/child
+/// relationships and, on UNIX, through the process group and session led
+/// by the process, so that those orphaned by an exited parent are found
+/// too.  A process started by [`start_detached`] leads its own process
+/// group for this reason.
+///
+/// On UNIX, every process in the tree is stopped before any is killed, so
+/// that none can start more processes while the tree is being found.  On
+/// Windows, children are killed before their parents.
+///
+/// # Errors
+///
+/// The same errors are returned as by [`try_kill`] if the process with the
+/// given identifier could not be killed.  Failing to kill any of its
+/// descendants, such as one which has exited in the meantime, is not
+/// reported.
+///
+/// [`start_detached`]: fn.start_detached.html
+/// [`try_kill`]: fn.try_kill.html
+pub fn kill_tree(pid: usize) -> Result<(), Error> {
+    freeze(pid)?;
+    let self_id = std::process::id() as usize;
+    let mut members = vec![pid];
+    let mut found = HashSet::new();
+    found.insert(pid);
+    found.insert(self_id);
+    // Keep looking until no new members turn up, in case any were started
+    // before their parents were stopped.
+    loop {
+        let tree = list_processes().collect::<ProcessTree>();
+        let candidates =
+            tree.processes().map(|process| process.id).collect::<Vec<_>>();
+        let mut new_members = group_members_internal(pid, &candidates);
+        for member in &members {
+            new_members.extend(
+                tree.descendants(*member).into_iter().map(|process| process.id),
+            );
+        }
+        new_members.retain(|member| found.insert(*member));
+        if new_members.is_empty() {
+            break;
+        }
+        for member in new_members {
+            let _ = freeze(member);
+            members.push(member);
+        }
+    }
+    for member in members.iter().skip(1).rev() {
+        let _ = try_kill(*member);
+    }
+    try_kill(pid)
+}
+
+/// This is how a process terminated by [`terminate`] came to exit.
+///
+/// [`terminate`]: fn.terminate.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Termination {
+    /// The process exited on its own within the grace period after it was
+    /// asked to shut down.
+    Graceful,
+
+    /// The process was still running at the end of the grace period and was
+    /// killed.
+    Forced,
+}
+
+/// Ask the process with the given `pid` (process identifier) to shut down
+/// cleanly by sending it [`Signal::Terminate`], and wait up to `grace` for it
+/// to exit.  If it is still running after that, kill it.  The result tells
+/// whether the process exited gracefully or had to be killed.
+///
+/// On Windows, which cannot ask a process to shut down, the process is
+/// killed immediately, and the result is always [`Termination::Forced`].
+///
+/// # Errors
+///
+/// The same errors are returned as by [`send_signal`], either when asking
+/// the process to shut down or when killing it.
+///
+/// [`Signal::Terminate`]: enum.Signal.html#variant.Terminate
+/// [`Termination::Forced`]: enum.Termination.html#variant.Forced
+/// [`send_signal`]: fn.send_signal.html
+pub fn terminate(
+    pid: usize,
+    grace: Duration,
+) -> Result<Termination, Error> {
+    send_signal(pid, Signal::Terminate)?;
+    if cfg!(target_os = "windows") {
+        return Ok(Termination::Forced);
+    }
+    if wait_for_exit(pid, grace) {
+        return Ok(Termination::Graceful);
+    }
+    match try_kill(pid) {
+        Ok(()) => Ok(Termination::Forced),
+        // The process exited after all, just before it would have been
+        // killed.
+        Err(Error::NoSuchProcess) => Ok(Termination::Graceful),
+        Err(error) => Err(error),
+    }
 }
 
 #[cfg(test)]
@@ -106,7 +720,6 @@ mod tests {
     use std::{
         convert::TryFrom as _,
         env::current_exe,
-        ffi::OsString,
         fs::{
             create_dir,
             read_to_string,
@@ -119,7 +732,11 @@ mod tests {
         },
         net::{
             Ipv4Addr,
+            Ipv6Addr,
+            SocketAddr,
             TcpListener,
+            TcpStream,
+            UdpSocket,
         },
         path::Path,
         thread::sleep,
@@ -155,23 +772,36 @@ mod tests {
         }
     }
 
-    #[test]
-    fn detached() {
-        // Set up the test area where the detached process will write
-        // its report files.
-        let test_area = TestArea::new();
-
-        // Find the mock subprocess.
-        let mock_subprocess = PathBuf::from(
+    fn mock_subprocess() -> PathBuf {
+        PathBuf::from(
             String::from_utf8_lossy(
                 &std::process::Command::new("cargo")
-                    .args(&["run", "--bin", "mock_subprocess", "--", "where"])
+                    .args(["run", "--bin", "mock_subprocess", "--", "where"])
                     .output()
                     .unwrap()
                     .stdout,
             )
             .to_string(),
-        );
+        )
+    }
+
+    fn spawn_sleeper() -> (std::process::Child, usize) {
+        let child = std::process::Command::new(mock_subprocess())
+            .arg("sleep")
+            .spawn()
+            .unwrap();
+        let pid = usize::try_from(child.id()).unwrap();
+        (child, pid)
+    }
+
+    #[test]
+    fn detached() {
+        // Set up the test area where the detached process will write
+        // its report files.
+        let test_area = TestArea::new();
+
+        // Find the mock subprocess.
+        let mock_subprocess = mock_subprocess();
 
         // Start the detached process.
         let args = vec![
@@ -221,18 +851,608 @@ mod tests {
                     .collect::<PathBuf>(),
             )
             .unwrap();
-            assert_eq!(0, handles.len(), "Handles: {}", handles);
+            assert_eq!(0, handles.len(), "Handles: {handles}");
         }
         drop(test_area);
     }
 
+    fn detached_env(command: &mut DetachedCommand) -> Vec<String> {
+        let test_area = TestArea::new();
+        let handle = command
+            .arg("detached")
+            .arg(test_area.path())
+            .start_with_handle()
+            .unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        BufReader::new(
+            File::open(
+                [test_area.path(), Path::new("env")]
+                    .iter()
+                    .collect::<PathBuf>(),
+            )
+            .unwrap(),
+        )
+        .lines()
+        .map(Result::unwrap)
+        .collect()
+    }
+
+    #[test]
+    fn detached_with_env() {
+        let env = detached_env(
+            DetachedCommand::new(mock_subprocess())
+                .env("RHYMUPROC_TEST", "abc def")
+                .env("RHYMUPROC_REMOVED", "ghi")
+                .env_remove("RHYMUPROC_REMOVED")
+                .env_remove("PATH"),
+        );
+        assert!(env.contains(&String::from("RHYMUPROC_TEST=abc def")));
+        assert!(!env.iter().any(|variable| variable.starts_with("PATH=")
+            || variable.starts_with("RHYMUPROC_REMOVED=")));
+        assert!(!env.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_cleared_env() {
+        let env = detached_env(
+            DetachedCommand::new(mock_subprocess())
+                .env("RHYMUPROC_BEFORE_CLEAR", "abc")
+                .env_clear()
+                .env("RHYMUPROC_TEST", "def"),
+        );
+        assert_eq!(vec![String::from("RHYMUPROC_TEST=def")], env);
+    }
+
+    #[test]
+    fn detached_with_current_dir() {
+        let test_area = TestArea::new();
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("detached")
+            .arg(test_area.path())
+            .current_dir(test_area.path())
+            .start_with_handle()
+            .unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        let cwd = std::fs::read_to_string(
+            [test_area.path(), Path::new("cwd")].iter().collect::<PathBuf>(),
+        )
+        .unwrap();
+        assert_eq!(
+            test_area.path().canonicalize().unwrap(),
+            Path::new(&cwd).canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn detached_with_nonexistent_current_dir() {
+        let test_area = TestArea::new();
+        let error = DetachedCommand::new(mock_subprocess())
+            .arg("sleep")
+            .current_dir(test_area.path().join("nonexistent"))
+            .start()
+            .unwrap_err();
+        match error {
+            Error::CurrentDir(error) | Error::CreateProcess(error) => {
+                assert!(error.raw_os_error().is_some());
+            },
+            error => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn detached_with_log_files() {
+        let test_area = TestArea::new();
+        let stdout_path = test_area.path().join("stdout.log");
+        let stderr_path = test_area.path().join("stderr.log");
+        std::fs::write(&stdout_path, "earlier output\n").unwrap();
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("print")
+            .stdout(&stdout_path)
+            .stderr(&stderr_path)
+            .start_with_handle()
+            .unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        assert_eq!(
+            "earlier output\noutput\n",
+            std::fs::read_to_string(&stdout_path).unwrap().replace('\r', "")
+        );
+        assert_eq!(
+            "error output\n",
+            std::fs::read_to_string(&stderr_path).unwrap().replace('\r', "")
+        );
+    }
+
+    #[test]
+    fn detached_with_unopenable_log_file() {
+        let test_area = TestArea::new();
+        let error = DetachedCommand::new(mock_subprocess())
+            .arg("sleep")
+            .stdout(test_area.path().join("nonexistent").join("stdout.log"))
+            .start()
+            .unwrap_err();
+        match error {
+            Error::Stdio(error) => {
+                assert_eq!(std::io::ErrorKind::NotFound, error.kind());
+            },
+            error => panic!("{}", error),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_clashing_fds() {
+        use std::os::unix::io::AsRawFd as _;
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut over_stdout = DetachedCommand::new(mock_subprocess());
+        over_stdout.arg("sleep").pass_fd_as(udp.as_raw_fd(), 1);
+        let mut over_listener = DetachedCommand::new(mock_subprocess());
+        over_listener
+            .arg("sleep")
+            .listen_fd(tcp.as_raw_fd())
+            .pass_fd_as(udp.as_raw_fd(), 3);
+        for command in &[over_stdout, over_listener] {
+            match command.start().unwrap_err() {
+                Error::PassFd(error) => {
+                    assert_eq!(Some(libc::EINVAL), error.raw_os_error());
+                },
+                error => panic!("{}", error),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_passed_fds() {
+        use std::os::unix::io::AsRawFd as _;
+        let test_area = TestArea::new();
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("detached")
+            .arg(test_area.path())
+            .listen_fd(tcp.as_raw_fd())
+            .pass_fd_as(udp.as_raw_fd(), 7)
+            .start_with_handle()
+            .unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        let read_record = |name| {
+            std::fs::read_to_string(
+                [test_area.path(), Path::new(name)].iter().collect::<PathBuf>(),
+            )
+            .unwrap()
+        };
+        let fds = read_record("handles")
+            .lines()
+            .map(|line| line.split(':').next().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![String::from("3"), String::from("7")], fds);
+        let env = read_record("env");
+        let pid = read_record("pid");
+        assert!(env.lines().any(|variable| variable == "LISTEN_FDS=1"));
+        assert!(env
+            .lines()
+            .any(|variable| variable == format!("LISTEN_PID={}", pid.trim())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_closed_fd() {
+        let error = DetachedCommand::new(mock_subprocess())
+            .arg("sleep")
+            .pass_fd(-1)
+            .start()
+            .unwrap_err();
+        match error {
+            Error::PassFd(error) => {
+                assert_eq!(Some(libc::EBADF), error.raw_os_error());
+            },
+            error => panic!("{}", error),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_current_user() {
+        let test_area = TestArea::new();
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("detached")
+            .arg(test_area.path())
+            .uid(uid)
+            .gid(gid)
+            .start_with_handle()
+            .unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        let ids = std::fs::read_to_string(
+            [test_area.path(), Path::new("ids")].iter().collect::<PathBuf>(),
+        )
+        .unwrap();
+        assert_eq!(format!("{uid}\n{gid}\n"), ids);
+    }
+
+    // Only the superuser may change to another user, so anyone else should
+    // see the failure to do so reported.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detached_as_other_user() {
+        const NOBODY: u32 = 65534;
+        let result = DetachedCommand::new("/bin/sleep")
+            .arg("30")
+            .uid(NOBODY)
+            .gid(NOBODY)
+            .groups([NOBODY])
+            .init_groups("nobody")
+            .start_with_handle();
+        if unsafe { libc::getuid() } != 0 {
+            match result {
+                Err(Error::SetGroups(error)) => {
+                    assert_eq!(Some(libc::EPERM), error.raw_os_error());
+                },
+                Err(error) => panic!("{}", error),
+                Ok(handle) => {
+                    let _ = handle.kill();
+                    panic!("started as another user without privileges");
+                },
+            }
+            return;
+        }
+        let handle = result.unwrap();
+        let status =
+            std::fs::read_to_string(format!("/proc/{}/status", handle.pid()))
+                .unwrap();
+        let field = |name| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap()
+                .split_whitespace()
+                .map(|id| id.parse::<u32>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![NOBODY; 4], field("Uid:"));
+        assert_eq!(vec![NOBODY; 4], field("Gid:"));
+        assert!(field("Groups:").iter().all(|group| *group == NOBODY));
+        handle.kill().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detached_as_other_user_without_groups() {
+        const NOBODY: u32 = 65534;
+        if unsafe { libc::getuid() } != 0 {
+            return;
+        }
+        let handle = DetachedCommand::new("/bin/sleep")
+            .arg("30")
+            .uid(NOBODY)
+            .gid(NOBODY)
+            .start_with_handle()
+            .unwrap();
+        let status =
+            std::fs::read_to_string(format!("/proc/{}/status", handle.pid()))
+                .unwrap();
+        let groups = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Groups:"))
+            .unwrap();
+        assert_eq!("", groups.trim());
+        handle.kill().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detached_with_groups_of_other_user() {
+        const NOBODY: u32 = 65534;
+        if unsafe { libc::getuid() } != 0 {
+            return;
+        }
+        let handle = DetachedCommand::new("/bin/sleep")
+            .arg("30")
+            .uid(NOBODY)
+            .init_groups("nobody")
+            .start_with_handle()
+            .unwrap();
+        let status =
+            std::fs::read_to_string(format!("/proc/{}/status", handle.pid()))
+                .unwrap();
+        let field = |name| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap()
+                .split_whitespace()
+                .map(|id| id.parse::<u32>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![NOBODY; 4], field("Gid:"));
+        assert!(field("Groups:").contains(&NOBODY));
+        assert!(!field("Groups:").contains(&0));
+        handle.kill().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_with_groups_of_unknown_user() {
+        let error = DetachedCommand::new(mock_subprocess())
+            .arg("sleep")
+            .init_groups("rhymuproc-nonexistent-user")
+            .start()
+            .unwrap_err();
+        match error {
+            Error::SetGroups(error) => {
+                assert_eq!(Some(libc::ENOENT), error.raw_os_error());
+            },
+            error => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn try_detached() {
+        let pid = try_start_detached(mock_subprocess(), ["sleep"]).unwrap();
+        assert_ne!(0, pid);
+        assert!(process_info(pid).is_some());
+        kill(pid);
+    }
+
+    #[test]
+    fn try_kill_child() {
+        let (mut child, pid) = spawn_sleeper();
+        try_kill(pid).unwrap();
+        assert!(!child.wait().unwrap().success());
+        assert!(matches!(try_kill(pid), Err(Error::NoSuchProcess)));
+    }
+
+    #[test]
+    fn kill_invalid_process_ids() {
+        for pid in [0, usize::MAX] {
+            assert!(matches!(try_kill(pid), Err(Error::NoSuchProcess)));
+            assert!(matches!(
+                send_signal(pid, Signal::Terminate),
+                Err(Error::NoSuchProcess)
+            ));
+        }
+    }
+
+    #[test]
+    fn send_terminate_signal() {
+        let (mut child, pid) = spawn_sleeper();
+        #[cfg(target_os = "windows")]
+        assert!(matches!(
+            send_signal(pid, Signal::Hangup),
+            Err(Error::UnsupportedSignal(Signal::Hangup))
+        ));
+        send_signal(pid, Signal::Terminate).unwrap();
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt as _;
+            assert_eq!(Some(libc::SIGTERM), status.signal());
+        }
+    }
+
+    #[test]
+    fn terminate_gracefully() {
+        let (mut child, pid) = spawn_sleeper();
+        let expected = if cfg!(target_os = "windows") {
+            Termination::Forced
+        } else {
+            Termination::Graceful
+        };
+        assert_eq!(expected, terminate(pid, Duration::from_secs(5)).unwrap());
+        child.wait().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminate_forced() {
+        let mut child = std::process::Command::new(mock_subprocess())
+            .arg("ignore-terminate")
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let pid = usize::try_from(child.id()).unwrap();
+        // Wait for the child to say it is ignoring the terminate signal.
+        let mut ready = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut ready)
+            .unwrap();
+        assert_eq!(
+            Termination::Forced,
+            terminate(pid, Duration::from_millis(100)).unwrap()
+        );
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn kill_process_tree() {
+        let mut child = std::process::Command::new(mock_subprocess())
+            .arg("parent")
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let child_id = usize::try_from(child.id()).unwrap();
+        let mut grandchild_id = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut grandchild_id)
+            .unwrap();
+        let grandchild_id = grandchild_id.trim().parse::<usize>().unwrap();
+        assert!(is_running(grandchild_id));
+        kill_tree(child_id).unwrap();
+        child.wait().unwrap();
+        for _ in 0..100 {
+            if !is_running(grandchild_id) {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        assert!(!is_running(grandchild_id));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_tree_spares_parent_without_session_leader() {
+        let test_area = TestArea::new();
+        let stdout_path = test_area.path().join("stdout.log");
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("sleep-with-child")
+            .stdout(&stdout_path)
+            .start_with_handle()
+            .unwrap();
+        let mut child_id = None;
+        for _ in 0..100 {
+            child_id = std::fs::read_to_string(&stdout_path)
+                .ok()
+                .and_then(|output| output.trim().parse::<usize>().ok());
+            if child_id.is_some() {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        let child_id = child_id.unwrap();
+        kill_tree(child_id).unwrap();
+        assert!(wait_for_exit(child_id, Duration::from_secs(5)));
+        assert!(is_running(handle.pid()));
+        kill_tree(handle.pid()).unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn wait_for_process_exit() {
+        let (mut child, pid) = spawn_sleeper();
+        assert!(!wait_for_exit(pid, Duration::from_millis(50)));
+        assert!(!poll_for_exit(pid, Duration::from_millis(50)));
+        kill(pid);
+        assert!(wait_for_exit(pid, Duration::from_secs(5)));
+        assert!(poll_for_exit(pid, Duration::from_secs(5)));
+        child.wait().unwrap();
+        assert!(wait_for_exit(pid, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn detached_with_handle() {
+        let handle =
+            start_detached_with_handle(mock_subprocess(), ["sleep"]).unwrap();
+        assert!(process_info(handle.pid()).is_some());
+        assert!(!handle.wait_for_exit(Duration::from_millis(50)));
+        handle.send_signal(Signal::Terminate).unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn handle_of_exited_process() {
+        let (mut child, pid) = spawn_sleeper();
+        let handle = ProcessHandle::open(pid).unwrap();
+        handle.kill().unwrap();
+        child.wait().unwrap();
+        assert!(handle.wait_for_exit(Duration::from_secs(5)));
+        assert!(matches!(handle.kill(), Err(Error::NoSuchProcess)));
+        assert!(matches!(ProcessHandle::open(pid), Err(Error::NoSuchProcess)));
+    }
+
+    #[test]
+    fn process_identity() {
+        let (mut child, pid) = spawn_sleeper();
+        let id = ProcessId::of(pid).unwrap();
+        assert_eq!(pid, id.pid());
+        assert_eq!(Some(id), process_info(pid).unwrap().process_id());
+        assert!(is_same_process(id));
+        let impostor = ProcessId::new(pid, id.start_time() + 1);
+        assert!(!is_same_process(impostor));
+        assert!(matches!(
+            kill_verified(impostor),
+            Err(Error::DifferentProcess)
+        ));
+        kill_verified(id).unwrap();
+        child.wait().unwrap();
+        assert!(!is_same_process(id));
+    }
+
+    #[test]
+    fn process_liveness() {
+        let (mut child, pid) = spawn_sleeper();
+        assert!(is_running(pid));
+        assert!(!is_zombie(pid));
+        kill(pid);
+        assert!(wait_for_exit(pid, Duration::from_secs(5)));
+        assert!(!is_running(pid));
+        #[cfg(unix)]
+        assert!(is_zombie(pid));
+        child.wait().unwrap();
+        assert!(!is_running(pid));
+        assert!(!is_zombie(pid));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn thread_is_not_a_process() {
+        let (tid_sender, tid_receiver) = std::sync::mpsc::channel();
+        let (done_sender, done_receiver) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+            tid_sender.send(usize::try_from(tid).unwrap()).unwrap();
+            let _ = done_receiver.recv();
+        });
+        let tid = tid_receiver.recv().unwrap();
+        assert_ne!(std::process::id() as usize, tid);
+        assert!(process_info(tid).is_none());
+        assert!(ProcessId::of(tid).is_none());
+        assert!(matches!(ProcessHandle::open(tid), Err(Error::NoSuchProcess)));
+        drop(done_sender);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn liveness_of_invalid_process_ids() {
+        for pid in [0, usize::MAX] {
+            assert!(!is_running(pid));
+            assert!(!is_zombie(pid));
+            assert!(wait_for_exit(pid, Duration::from_secs(0)));
+            assert!(matches!(
+                ProcessHandle::open(pid),
+                Err(Error::NoSuchProcess)
+            ));
+            #[cfg(unix)]
+            assert!(process_info(pid).is_none());
+        }
+    }
+
+    #[test]
+    fn detached_nonexistent_program() {
+        let test_area = TestArea::new();
+        let path = test_area.path().join("nonexistent");
+        match try_start_detached(&path, ["sleep"]).unwrap_err() {
+            Error::Exec(error) | Error::CreateProcess(error) => {
+                assert_eq!(std::io::ErrorKind::NotFound, error.kind());
+            },
+            error => panic!("{}", error),
+        }
+        assert_eq!(0, start_detached(&path, ["sleep"]));
+    }
+
+    #[test]
+    fn detached_with_null_in_input() {
+        let with_null_program = DetachedCommand::new("mock\0subprocess");
+        let mut with_null_arg = DetachedCommand::new(mock_subprocess());
+        with_null_arg.arg("sle\0ep");
+        let mut with_null_env = DetachedCommand::new(mock_subprocess());
+        with_null_env.arg("sleep").env("RHYMUPROC_TEST", "a\0b");
+        for command in &[with_null_program, with_null_arg, with_null_env] {
+            assert!(matches!(command.start(), Err(Error::InvalidInput)));
+        }
+    }
+
     #[test]
     fn find_self_by_image_path() {
         let mut processes = list_processes();
         let self_path = current_exe().unwrap().canonicalize().unwrap();
         let self_id = usize::try_from(std::process::id()).unwrap();
         assert!(processes.any(|process| {
-            process.image == self_path && process.id == self_id
+            process.image.as_deref() == Some(&self_path)
+                && process.id == self_id
         }));
     }
 
@@ -246,4 +1466,241 @@ mod tests {
             process.tcp_server_ports.contains(&port) && process.id == self_id
         }));
     }
+
+    #[test]
+    fn process_info_for_self() {
+        let self_path = current_exe().unwrap().canonicalize().unwrap();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        let process = process_info(self_id).unwrap();
+        assert_eq!(self_id, process.id);
+        assert_eq!(Some(self_path), process.image);
+    }
+
+    #[test]
+    fn process_info_for_exited_process() {
+        let mut child =
+            std::process::Command::new(mock_subprocess()).spawn().unwrap();
+        let pid = usize::try_from(child.id()).unwrap();
+        child.wait().unwrap();
+        assert!(process_info(pid).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_info_for_zombie_process() {
+        let (mut child, pid) = spawn_sleeper();
+        child.kill().unwrap();
+        // Until the child is reaped it remains a zombie, without an image.
+        let mut process = process_info(pid).unwrap();
+        for _ in 0..100 {
+            if process.image.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            process = process_info(pid).unwrap();
+        }
+        assert_eq!(None, process.image);
+        assert_eq!(OsStr::new("mock_subprocess"), process.name);
+        assert!(list_processes().any(|process| process.id == pid));
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn process_info_args() {
+        #[allow(unused_mut)]
+        let mut args = vec![
+            OsString::from("sleep"),
+            OsString::from("abc"),
+            OsString::from("def ghi"),
+        ];
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt as _;
+            args.push(OsStr::from_bytes(b"not \xFF UTF-8").to_owned());
+        }
+        let mut child = std::process::Command::new(mock_subprocess())
+            .args(&args)
+            .spawn()
+            .unwrap();
+        let child_id = usize::try_from(child.id()).unwrap();
+        // The child may not have executed the mock subprocess yet, in which
+        // case it still has our own arguments.
+        let mut process = process_info(child_id).unwrap();
+        for _ in 0..100 {
+            if process.args.get(1..) == Some(&args[..]) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            process = process_info(child_id).unwrap();
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(args.len() + 1, process.args.len());
+        assert_eq!(args, process.args[1..]);
+    }
+
+    #[test]
+    fn process_tree() {
+        let (mut child, child_id) = spawn_sleeper();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        let tree = list_processes().collect::<ProcessTree>();
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(Some(self_id), tree.get(child_id).unwrap().parent_id);
+        assert_eq!(self_id, tree.parent(child_id).unwrap().id);
+        assert!(tree.children(self_id).any(|process| process.id == child_id));
+        assert_eq!(self_id, tree.ancestors(child_id)[0].id);
+        assert!(tree
+            .ancestors(child_id)
+            .iter()
+            .any(|process| tree.roots().any(|root| root.id == process.id)));
+        assert!(tree
+            .descendants(self_id)
+            .iter()
+            .any(|process| process.id == child_id));
+        assert!(tree.descendants(child_id).is_empty());
+    }
+
+    #[test]
+    fn find_self_listening_on_port() {
+        let tcp = TcpListener::bind((Ipv6Addr::UNSPECIFIED, 0)).unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        let owners = process_listening_on(port);
+        assert_eq!(1, owners.len());
+        assert_eq!(self_id, owners[0].id);
+        assert!(owners[0].tcp6_server_ports.contains(&port));
+        drop(tcp);
+        assert!(process_listening_on(port).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_all_owners_of_shared_listener() {
+        use std::os::unix::io::AsRawFd as _;
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        let handle = DetachedCommand::new(mock_subprocess())
+            .arg("sleep")
+            .pass_fd(tcp.as_raw_fd())
+            .start_with_handle()
+            .unwrap();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        let mut owners = process_listening_on(port)
+            .into_iter()
+            .map(|owner| owner.id)
+            .collect::<Vec<_>>();
+        owners.sort_unstable();
+        let mut expected = vec![self_id, handle.pid()];
+        expected.sort_unstable();
+        assert_eq!(expected, owners);
+        handle.kill().unwrap();
+    }
+
+    #[test]
+    fn find_self_by_tcp_server_address() {
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = tcp.local_addr().unwrap();
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.tcp_server_addresses.contains(&address)
+                && !process.tcp_server_addresses.contains(&SocketAddr::from((
+                    Ipv4Addr::UNSPECIFIED,
+                    address.port(),
+                )))
+                && process.tcp_server_ports.contains(&address.port())
+                && process.id == self_id
+        }));
+    }
+
+    #[test]
+    fn find_self_by_tcp_connection() {
+        let tcp = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let server_address = tcp.local_addr().unwrap();
+        let client = TcpStream::connect(server_address).unwrap();
+        let client_address = client.local_addr().unwrap();
+        let (_server, _) = tcp.accept().unwrap();
+        let outbound = TcpConnection {
+            local_address: client_address,
+            remote_address: server_address,
+            state: TcpState::Established,
+        };
+        let accepted = TcpConnection {
+            local_address: server_address,
+            remote_address: client_address,
+            state: TcpState::Established,
+        };
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.tcp_connections.contains(&outbound)
+                && process.tcp_connections.contains(&accepted)
+                && process.id == self_id
+        }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_self_by_unix_socket_listener() {
+        let test_area = TestArea::new();
+        let path = [test_area.path(), Path::new("listener.sock")]
+            .iter()
+            .collect::<PathBuf>();
+        let _unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let name = UnixSocketName::Path(path);
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.unix_socket_listeners.contains(&name)
+                && process.id == self_id
+        }));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn find_self_by_abstract_unix_socket_listener() {
+        use std::os::{
+            linux::net::SocketAddrExt as _,
+            unix::net::{
+                SocketAddr,
+                UnixListener,
+            },
+        };
+        let abstract_name = uuid::Uuid::new_v4().to_string();
+        let address =
+            SocketAddr::from_abstract_name(abstract_name.as_bytes()).unwrap();
+        let _unix = UnixListener::bind_addr(&address).unwrap();
+        let name = UnixSocketName::Abstract(OsString::from(abstract_name));
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.unix_socket_listeners.contains(&name)
+                && process.id == self_id
+        }));
+    }
+
+    #[test]
+    fn find_self_by_udp_port() {
+        let udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = udp.local_addr().unwrap().port();
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.udp_ports.contains(&port) && process.id == self_id
+        }));
+    }
+
+    #[test]
+    fn find_self_by_tcp6_server_port() {
+        let tcp = TcpListener::bind((Ipv6Addr::UNSPECIFIED, 0)).unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        let mut processes = list_processes();
+        let self_id = usize::try_from(std::process::id()).unwrap();
+        assert!(processes.any(|process| {
+            process.tcp_server_ports.contains(&port)
+                && process.tcp6_server_ports.contains(&port)
+                && process.id == self_id
+        }));
+    }
 }
@@ -3,18 +3,56 @@ use std::{
         HashMap,
         HashSet,
     },
+    convert::TryFrom as _,
+    ffi::{
+        OsStr,
+        OsString,
+    },
     fs::{
+        read,
         read_dir,
         read_link,
         File,
     },
     io::{
+        self,
         BufRead as _,
         BufReader,
+        Read as _,
+    },
+    iter::FromIterator,
+    net::{
+        Ipv4Addr,
+        Ipv6Addr,
+        SocketAddr,
     },
+    os::unix::ffi::OsStrExt as _,
+    path::Path,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::{
+    is_running_internal,
+    poll_for_exit,
+    send_signal_internal,
+    signal_error,
+    signal_number,
+    tcp_server_ports,
+    to_pid_t,
+    Error,
+    ProcessInfo,
+    Signal,
+    TcpConnection,
+    TcpState,
+    UnixSocketName,
 };
 
-use crate::ProcessInfo;
+const TCP_LISTEN: u8 = 10;
+const SO_ACCEPTCON: u32 = 0x0001_0000;
+const SOCK_DGRAM: u16 = 2;
 
 fn match_socket_fd_name<T: AsRef<str>>(fd_name: T) -> Option<usize> {
     fd_name
@@ -23,14 +61,11 @@ fn match_socket_fd_name<T: AsRef<str>>(fd_name: T) -> Option<usize> {
         .and_then(|socket_fd_name_suffix| {
             socket_fd_name_suffix.strip_suffix("]")
         })
-        .and_then(|inode| usize::from_str_radix(&inode, 10).ok())
+        .and_then(|inode| inode.parse::<usize>().ok())
 }
 
-fn tcp_server_ports_for_process(
-    pid: usize,
-    inodes_to_tcp_server_ports: &HashMap<usize, u16>,
-) -> HashSet<u16> {
-    read_dir(format!("/proc/{}/fd/", pid))
+fn socket_inodes_for_process(pid: usize) -> HashSet<usize> {
+    read_dir(format!("/proc/{pid}/fd/"))
         .map(|dir_entries| {
             dir_entries
                 .filter_map(|dir_entry| {
@@ -44,82 +79,541 @@ fn tcp_server_ports_for_process(
                                 .ok()
                                 .and_then(match_socket_fd_name)
                         })
-                        .and_then(|inode| {
-                            inodes_to_tcp_server_ports.get(&inode).copied()
-                        })
                 })
                 .collect()
         })
         .unwrap_or_default()
 }
 
-pub fn list_processes_internal() -> impl Iterator<Item = ProcessInfo> {
-    let mut inodes_to_tcp_server_ports = HashMap::new();
-    if let Ok(tcp_table) = File::open("/proc/net/tcp") {
-        for line in BufReader::new(tcp_table).lines().filter_map(Result::ok) {
+fn parse_socket_address<T: AsRef<str>>(address_info: T) -> Option<SocketAddr> {
+    let mut address_parts = address_info.as_ref().split(':');
+    let address = address_parts.next()?;
+    let port = u16::from_str_radix(address_parts.next()?, 16).ok()?;
+    // The kernel prints addresses as a series of 32-bit words, each in host
+    // byte order.
+    let address_bytes = (0..address.len())
+        .step_by(8)
+        .map(|i| {
+            address
+                .get(i..i + 8)
+                .and_then(|word| u32::from_str_radix(word, 16).ok())
+                .map(u32::to_ne_bytes)
+        })
+        .collect::<Option<Vec<_>>>()?
+        .concat();
+    match address_bytes.len() {
+        4 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(&address_bytes);
+            Some(SocketAddr::from((Ipv4Addr::from(octets), port)))
+        },
+        16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&address_bytes);
+            Some(SocketAddr::from((Ipv6Addr::from(octets), port)))
+        },
+        _ => None,
+    }
+}
+
+fn tcp_state(status: u8) -> TcpState {
+    match status {
+        1 => TcpState::Established,
+        2 => TcpState::SynSent,
+        3 | 12 => TcpState::SynReceived,
+        4 => TcpState::FinWait1,
+        5 => TcpState::FinWait2,
+        6 => TcpState::TimeWait,
+        7 => TcpState::Closed,
+        8 => TcpState::CloseWait,
+        9 => TcpState::LastAck,
+        TCP_LISTEN => TcpState::Listen,
+        11 => TcpState::Closing,
+        _ => TcpState::Unknown,
+    }
+}
+
+fn read_socket_table<P, F, T>(
+    path: P,
+    mut select: F,
+    inodes_to_entries: &mut HashMap<usize, T>,
+) where
+    P: AsRef<Path>,
+    F: FnMut(SocketAddr, SocketAddr, u8) -> Option<T>,
+{
+    if let Ok(socket_table) = File::open(path) {
+        for line in BufReader::new(socket_table).lines().map_while(Result::ok) {
             let mut line_parts = line.split_whitespace().skip(1);
-            let port_info = line_parts.next();
-            let mut line_parts = line_parts.skip(1);
+            let local_address_info = line_parts.next();
+            let remote_address_info = line_parts.next();
             let status = line_parts.next();
             let mut line_parts = line_parts.skip(5);
             let inode = line_parts.next();
-            if let (Some(port_info), Some(status), Some(inode)) =
-                (port_info, status, inode)
+            if let (
+                Some(local_address_info),
+                Some(remote_address_info),
+                Some(status),
+                Some(inode),
+            ) = (local_address_info, remote_address_info, status, inode)
             {
                 if let (
-                    Some(Ok(local_port)),
-                    Ok(10), // TCP_LISTEN
+                    Some(local_address),
+                    Some(remote_address),
+                    Ok(status),
                     Ok(inode),
                 ) = (
-                    port_info
-                        .split(':')
-                        .nth(1)
-                        .map(|local_port| u16::from_str_radix(local_port, 16)),
+                    parse_socket_address(local_address_info),
+                    parse_socket_address(remote_address_info),
                     u8::from_str_radix(status, 16),
-                    usize::from_str_radix(inode, 10),
+                    inode.parse::<usize>(),
                 ) {
-                    inodes_to_tcp_server_ports.insert(inode, local_port);
+                    if let Some(entry) =
+                        select(local_address, remote_address, status)
+                    {
+                        inodes_to_entries.insert(inode, entry);
+                    }
                 }
             }
         }
     }
-    read_dir("/proc/").into_iter().flatten().filter_map(move |dir_entry| {
+}
+
+fn entries_for_socket_inodes<T, C>(
+    socket_inodes: &HashSet<usize>,
+    inodes_to_entries: &HashMap<usize, T>,
+) -> C
+where
+    T: Clone,
+    C: FromIterator<T>,
+{
+    socket_inodes
+        .iter()
+        .filter_map(|inode| inodes_to_entries.get(inode).cloned())
+        .collect()
+}
+
+fn parse_unix_socket_name(name: &[u8]) -> UnixSocketName {
+    // The kernel shows the leading null byte of an abstract name as '@'.
+    match name.split_first() {
+        Some((b'@', abstract_name)) => {
+            UnixSocketName::Abstract(OsStr::from_bytes(abstract_name).into())
+        },
+        _ => UnixSocketName::Path(OsStr::from_bytes(name).into()),
+    }
+}
+
+fn read_unix_socket_listeners() -> HashMap<usize, UnixSocketName> {
+    let mut inodes_to_unix_socket_names = HashMap::new();
+    let mut unix_table = Vec::new();
+    if File::open("/proc/net/unix")
+        .and_then(|mut file| file.read_to_end(&mut unix_table))
+        .is_err()
+    {
+        return inodes_to_unix_socket_names;
+    }
+    for line in unix_table.split(|byte| *byte == b'\n').skip(1) {
+        // The path is last and may itself contain spaces, so peel off the
+        // other fields one by one and take whatever is left as the path.
+        let mut rest = line;
+        let mut fields = Vec::new();
+        for _ in 0..7 {
+            let start = rest
+                .iter()
+                .position(|byte| *byte != b' ')
+                .unwrap_or(rest.len());
+            rest = &rest[start..];
+            let end = rest
+                .iter()
+                .position(|byte| *byte == b' ')
+                .unwrap_or(rest.len());
+            fields.push(String::from_utf8_lossy(&rest[..end]));
+            rest = rest.get(end + 1..).unwrap_or_default();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        if let (Ok(flags), Ok(socket_type), Ok(inode)) = (
+            u32::from_str_radix(&fields[3], 16),
+            u16::from_str_radix(&fields[4], 16),
+            fields[6].parse::<usize>(),
+        ) {
+            if ((flags & SO_ACCEPTCON) != 0) || (socket_type == SOCK_DGRAM) {
+                inodes_to_unix_socket_names
+                    .insert(inode, parse_unix_socket_name(rest));
+            }
+        }
+    }
+    inodes_to_unix_socket_names
+}
+
+// Each of these maps socket inodes to what is known about the sockets.
+struct SocketTables {
+    tcp_server_addresses: HashMap<usize, SocketAddr>,
+    tcp_connections: HashMap<usize, TcpConnection>,
+    udp_addresses: HashMap<usize, SocketAddr>,
+    unix_socket_listeners: HashMap<usize, UnixSocketName>,
+}
+
+impl SocketTables {
+    fn read() -> Self {
+        let mut inodes_to_tcp_sockets = HashMap::new();
+        for path in &["/proc/net/tcp", "/proc/net/tcp6"] {
+            read_socket_table(
+                path,
+                |local_address, remote_address, status| {
+                    Some(TcpConnection {
+                        local_address,
+                        remote_address,
+                        state: tcp_state(status),
+                    })
+                },
+                &mut inodes_to_tcp_sockets,
+            );
+        }
+        let (inodes_to_tcp_listeners, inodes_to_tcp_connections): (
+            HashMap<_, _>,
+            HashMap<_, _>,
+        ) = inodes_to_tcp_sockets
+            .into_iter()
+            .partition(|(_, socket)| socket.state == TcpState::Listen);
+        let mut inodes_to_udp_addresses = HashMap::new();
+        for path in &["/proc/net/udp", "/proc/net/udp6"] {
+            read_socket_table(
+                path,
+                |local_address, _, _| {
+                    if local_address.port() == 0 {
+                        None
+                    } else {
+                        Some(local_address)
+                    }
+                },
+                &mut inodes_to_udp_addresses,
+            );
+        }
+        Self {
+            tcp_server_addresses: inodes_to_tcp_listeners
+                .into_iter()
+                .map(|(inode, listener)| (inode, listener.local_address))
+                .collect(),
+            tcp_connections: inodes_to_tcp_connections,
+            udp_addresses: inodes_to_udp_addresses,
+            unix_socket_listeners: read_unix_socket_listeners(),
+        }
+    }
+}
+
+fn list_process_ids() -> impl Iterator<Item = usize> {
+    read_dir("/proc/").into_iter().flatten().filter_map(|dir_entry| {
         dir_entry
             .ok()
             .and_then(|dir_entry| {
                 dir_entry.file_name().to_str().map(str::to_owned)
             })
             .and_then(|file_name| file_name.parse::<usize>().ok())
-            .and_then(|id| {
-                read_link(format!("/proc/{}/exe", id))
-                    .ok()
-                    .map(|image| (id, image))
-            })
-            .map(|(id, image)| ProcessInfo {
-                id,
-                image,
-                tcp_server_ports: tcp_server_ports_for_process(
-                    id,
-                    &inodes_to_tcp_server_ports,
-                ),
-            })
     })
 }
 
-pub fn close_all_files_except(keep_open: libc::c_int) {
-    if let Ok(dir_entries) = read_dir("/proc/self/fd/") {
-        for dir_entry in dir_entries {
-            if let Some(fd_num) = dir_entry
-                .ok()
-                .and_then(|dir_entry| {
-                    dir_entry.file_name().to_str().map(str::to_owned)
-                })
-                .and_then(|file_name| file_name.parse::<libc::c_int>().ok())
-            {
-                if fd_num != keep_open {
-                    unsafe { libc::close(fd_num) };
-                }
+// `/proc/<tid>` also exists for every thread, even though only the main
+// thread, whose identifier is that of its process, is listed.
+fn is_process(pid: usize) -> bool {
+    read(format!("/proc/{pid}/status")).ok().is_some_and(|status| {
+        String::from_utf8_lossy(&status)
+            .lines()
+            .find_map(|line| line.strip_prefix("Tgid:"))
+            .and_then(|tgid| tgid.trim().parse::<usize>().ok())
+            == Some(pid)
+    })
+}
+
+// Read the fields of `/proc/<pid>/stat` which follow the command name.  The
+// command name is in parentheses and may itself contain spaces and
+// parentheses, so everything up to the last closing parenthesis is skipped.
+fn read_stat_fields(pid: usize) -> Option<Vec<String>> {
+    if !is_process(pid) {
+        return None;
+    }
+    let stat = read(format!("/proc/{pid}/stat")).ok()?;
+    let stat = String::from_utf8_lossy(&stat);
+    let (_, fields) = stat.rsplit_once(')')?;
+    Some(fields.split_whitespace().map(str::to_owned).collect())
+}
+
+fn parent_id(stat_fields: &[String]) -> Option<usize> {
+    stat_fields
+        .get(1)
+        .and_then(|parent_id| parent_id.parse::<usize>().ok())
+        .filter(|parent_id| *parent_id != 0)
+}
+
+// The start time is field 22 of the stat file, counting from the identifier.
+fn start_time(stat_fields: &[String]) -> Option<u64> {
+    stat_fields.get(19).and_then(|start_time| start_time.parse::<u64>().ok())
+}
+
+pub fn start_time_internal(pid: usize) -> Option<u64> {
+    read_stat_fields(pid).and_then(|stat_fields| start_time(&stat_fields))
+}
+
+fn read_name(pid: usize) -> OsString {
+    read(format!("/proc/{pid}/comm"))
+        .map(|comm| {
+            OsStr::from_bytes(comm.strip_suffix(b"\n").unwrap_or(&comm))
+                .to_owned()
+        })
+        .unwrap_or_default()
+}
+
+// Each argument is terminated by a null byte.  Reading the file fails or
+// comes up empty if access is denied or the process is a kernel thread, in
+// which case no arguments are reported.
+fn read_args(pid: usize) -> Vec<OsString> {
+    let cmdline = read(format!("/proc/{pid}/cmdline")).unwrap_or_default();
+    if cmdline.is_empty() {
+        return Vec::new();
+    }
+    cmdline
+        .strip_suffix(&[0])
+        .unwrap_or(&cmdline)
+        .split(|byte| *byte == 0)
+        .map(|arg| OsStr::from_bytes(arg).to_owned())
+        .collect()
+}
+
+#[allow(clippy::similar_names)]
+fn process_info(
+    id: usize,
+    socket_tables: &SocketTables,
+) -> Option<ProcessInfo> {
+    // The stat file is readable for every process, including kernel threads,
+    // zombies and processes of other users, so it tells whether the process
+    // is still there.
+    let stat_fields = read_stat_fields(id)?;
+    let socket_inodes = socket_inodes_for_process(id);
+    let tcp_server_addresses = entries_for_socket_inodes(
+        &socket_inodes,
+        &socket_tables.tcp_server_addresses,
+    );
+    let (tcp_server_ports, tcp6_server_ports) =
+        tcp_server_ports(&tcp_server_addresses);
+    let udp_ports = entries_for_socket_inodes::<_, Vec<_>>(
+        &socket_inodes,
+        &socket_tables.udp_addresses,
+    )
+    .iter()
+    .map(SocketAddr::port)
+    .collect();
+    Some(ProcessInfo {
+        id,
+        parent_id: parent_id(&stat_fields),
+        start_time: start_time(&stat_fields),
+        name: read_name(id),
+        image: read_link(format!("/proc/{id}/exe")).ok(),
+        args: read_args(id),
+        tcp_server_addresses,
+        tcp_server_ports,
+        tcp6_server_ports,
+        tcp_connections: entries_for_socket_inodes(
+            &socket_inodes,
+            &socket_tables.tcp_connections,
+        ),
+        udp_ports,
+        unix_socket_listeners: entries_for_socket_inodes(
+            &socket_inodes,
+            &socket_tables.unix_socket_listeners,
+        ),
+    })
+}
+
+pub fn list_processes_internal() -> impl Iterator<Item = ProcessInfo> {
+    let socket_tables = SocketTables::read();
+    list_process_ids().filter_map(move |id| process_info(id, &socket_tables))
+}
+
+pub fn process_info_internal(pid: usize) -> Option<ProcessInfo> {
+    process_info(pid, &SocketTables::read())
+}
+
+pub fn process_listening_on_internal(port: u16) -> Vec<ProcessInfo> {
+    let socket_tables = SocketTables::read();
+    let listener_inodes = socket_tables
+        .tcp_server_addresses
+        .iter()
+        .filter(|(_, address)| address.port() == port)
+        .map(|(inode, _)| *inode)
+        .collect::<HashSet<_>>();
+    if listener_inodes.is_empty() {
+        return Vec::new();
+    }
+    // Every process must be scanned, since one listening socket may be
+    // shared by several processes, such as the workers of a pre-forked
+    // server.
+    list_process_ids()
+        .filter(|id| {
+            socket_inodes_for_process(*id)
+                .iter()
+                .any(|inode| listener_inodes.contains(inode))
+        })
+        .filter_map(|id| process_info(id, &socket_tables))
+        .collect()
+}
+
+// A process file descriptor refers to one particular process, even if its
+// identifier is reused after it exits, and becomes readable once it exits.
+// They were added in Linux 5.3, so fall back to using the identifier on older
+// kernels.
+pub struct ProcessHandleInternal {
+    pid: usize,
+    pidfd: Option<libc::c_int>,
+}
+
+impl ProcessHandleInternal {
+    pub fn open(pid: usize) -> Result<Self, Error> {
+        let pid_t = to_pid_t(pid).ok_or(Error::NoSuchProcess)?;
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid_t, 0) };
+        if pidfd >= 0 {
+            #[allow(clippy::cast_possible_truncation)]
+            return Ok(Self {
+                pid,
+                pidfd: Some(pidfd as libc::c_int),
+            });
+        }
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::ENOSYS) => {},
+            // A thread other than the main one of its process has an
+            // identifier of its own, but is not a process.  Older kernels
+            // report it as invalid, and newer ones as not found.
+            Some(libc::ESRCH | libc::EINVAL | libc::ENOENT) => {
+                return Err(Error::NoSuchProcess)
+            },
+            Some(libc::EPERM) => return Err(Error::PermissionDenied),
+            _ => return Err(Error::OpenProcess(error)),
+        }
+        if is_running_internal(pid) {
+            Ok(Self::from_pid(pid))
+        } else {
+            Err(Error::NoSuchProcess)
+        }
+    }
+
+    // Without a pidfd, the process is only known by its identifier, which
+    // could be reused once it exits.
+    pub fn from_pid(pid: usize) -> Self {
+        Self {
+            pid,
+            pidfd: None,
+        }
+    }
+
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    pub fn send_signal(
+        &self,
+        signal: Signal,
+    ) -> Result<(), Error> {
+        let Some(pidfd) = self.pidfd else {
+            return send_signal_internal(self.pid, signal);
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                pidfd,
+                signal_number(signal),
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(signal_error(io::Error::last_os_error()))
+        }
+    }
+
+    pub fn wait_for_exit(
+        &self,
+        timeout: Duration,
+    ) -> bool {
+        let Some(pidfd) = self.pidfd else {
+            return poll_for_exit(self.pid, timeout);
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // Round up, so as not to give up before the deadline.
+            let timeout_ms =
+                libc::c_int::try_from(remaining.as_micros().div_ceil(1000))
+                    .unwrap_or(libc::c_int::MAX);
+            let mut poll_fd = libc::pollfd {
+                fd: pidfd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe {
+                libc::poll(std::ptr::addr_of_mut!(poll_fd), 1, timeout_ms)
             };
+            match ready {
+                0 => break false,
+                ready if ready > 0 => break true,
+                _ if io::Error::last_os_error().raw_os_error()
+                    == Some(libc::EINTR) => {},
+                _ => break poll_for_exit(self.pid, remaining),
+            }
+        }
+    }
+}
+
+impl Drop for ProcessHandleInternal {
+    fn drop(&mut self) {
+        if let Some(pidfd) = self.pidfd {
+            unsafe { libc::close(pidfd) };
+        }
+    }
+}
+
+pub fn wait_for_exit_internal(
+    pid: usize,
+    timeout: Duration,
+) -> bool {
+    match ProcessHandleInternal::open(pid) {
+        Ok(handle) => handle.wait_for_exit(timeout),
+        Err(Error::NoSuchProcess) => true,
+        Err(_) => poll_for_exit(pid, timeout),
+    }
+}
+
+// The state of the process is the first field after its name.
+pub fn is_zombie_internal(pid: usize) -> bool {
+    read_stat_fields(pid)
+        .and_then(|stat_fields| stat_fields.into_iter().next())
+        .as_deref()
+        == Some("Z")
+}
+
+pub fn close_all_files_except(keep_open: &[libc::c_int]) {
+    // Collect the descriptors first, since closing the one used to read the
+    // directory while still iterating it would pull it out from under us.
+    let fds = read_dir("/proc/self/fd/")
+        .map(|dir_entries| {
+            dir_entries
+                .filter_map(|dir_entry| {
+                    dir_entry
+                        .ok()
+                        .and_then(|dir_entry| {
+                            dir_entry.file_name().to_str().map(str::to_owned)
+                        })
+                        .and_then(|file_name| {
+                            file_name.parse::<libc::c_int>().ok()
+                        })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for fd_num in fds {
+        if !keep_open.contains(&fd_num) {
+            unsafe { libc::close(fd_num) };
         }
     }
 }
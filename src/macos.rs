@@ -1,23 +1,57 @@
-use crate::ProcessInfo;
+use crate::{
+    is_running_internal,
+    poll_for_exit,
+    send_signal_internal,
+    tcp_server_ports,
+    to_pid_t,
+    Error,
+    ProcessInfo,
+    Signal,
+    TcpConnection,
+    TcpState,
+    UnixSocketName,
+};
 use std::{
     borrow::Borrow,
     collections::HashSet,
-    ffi::c_void,
-    os::raw::{
-        c_int,
-        c_longlong,
-        c_short,
-        c_uint,
-        c_ushort,
+    ffi::{
+        c_void,
+        OsStr,
+        OsString,
+    },
+    net::{
+        Ipv4Addr,
+        Ipv6Addr,
+        SocketAddr,
+    },
+    os::{
+        raw::{
+            c_int,
+            c_longlong,
+            c_short,
+            c_uint,
+            c_ushort,
+        },
+        unix::ffi::OsStrExt as _,
     },
     path::PathBuf,
+    time::Duration,
 };
 
 const AF_INET: c_int = 2;
+const AF_INET6: c_int = 30;
+const SOCKINFO_IN: c_int = 1;
 const SOCKINFO_TCP: c_int = 2;
+const SOCKINFO_UN: c_int = 3;
+const SOCK_DGRAM: c_int = 2;
+const SO_ACCEPTCONN: c_short = 0x0002;
+const IPPROTO_UDP: c_int = 17;
 const PROC_PIDPATHINFO_MAXSIZE: usize = 4096;
 const PROC_PIDLISTFDS: c_int = 1;
 const PROC_PIDFDSOCKETINFO: c_int = 3;
+const PROC_PIDTBSDINFO: c_int = 3;
+const MAXCOMLEN: usize = 16;
+const SZOMB: u32 = 5;
 const PROX_FDTYPE_SOCKET: u32 = 2;
 const TSI_T_NTIMERS: usize = 4;
 const SOCK_MAXADDRLEN: usize = 255;
@@ -311,6 +345,33 @@ struct socket_fdinfo {
     psi: socket_info,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct proc_bsdinfo {
+    pbi_flags: u32,
+    pbi_status: u32,
+    pbi_xstatus: u32,
+    pbi_pid: u32,
+    pbi_ppid: u32,
+    pbi_uid: uid_t,
+    pbi_gid: gid_t,
+    pbi_ruid: uid_t,
+    pbi_rgid: gid_t,
+    pbi_svuid: uid_t,
+    pbi_svgid: gid_t,
+    rfu_1: u32,
+    pbi_comm: [u8; MAXCOMLEN],
+    pbi_name: [[u8; MAXCOMLEN]; 2],
+    pbi_nfiles: u32,
+    pbi_pgid: u32,
+    pbi_pjobc: u32,
+    e_tdev: u32,
+    e_tpgid: u32,
+    pbi_nice: i32,
+    pbi_start_tvsec: u64,
+    pbi_start_tvusec: u64,
+}
+
 #[link(name = "proc")]
 extern "C" {
     fn proc_listallpids(
@@ -358,15 +419,132 @@ fn list_process_ids() -> Vec<pid_t> {
     pids
 }
 
-fn process_image(pid: pid_t) -> PathBuf {
+fn process_image(pid: pid_t) -> Option<PathBuf> {
     let mut name_chars = vec![0; PROC_PIDPATHINFO_MAXSIZE];
     let buffer_size = unsafe {
         #[allow(clippy::cast_possible_truncation)]
         proc_pidpath(pid, name_chars.as_mut_ptr(), name_chars.len() as u32)
     };
+    if buffer_size <= 0 {
+        return None;
+    }
     #[allow(clippy::cast_sign_loss)]
     name_chars.truncate(buffer_size as usize);
-    PathBuf::from(String::from_utf8_lossy(&name_chars).to_string())
+    Some(PathBuf::from(String::from_utf8_lossy(&name_chars).to_string()))
+}
+
+fn process_start_time(bsd_info: &proc_bsdinfo) -> u64 {
+    bsd_info.pbi_start_tvsec * 1_000_000 + bsd_info.pbi_start_tvusec
+}
+
+pub fn start_time_internal(pid: usize) -> Option<u64> {
+    let bsd_info = process_bsd_info(to_pid_t(pid)?);
+    bsd_info.as_ref().map(process_start_time)
+}
+
+// The kernel keeps a longer name as well as the command name, which is cut
+// off at MAXCOMLEN characters, so prefer the longer one where it is set.
+fn process_name(bsd_info: &proc_bsdinfo) -> OsString {
+    let name = bsd_info
+        .pbi_name
+        .iter()
+        .flatten()
+        .copied()
+        .take_while(|ch| *ch != 0)
+        .collect::<Vec<_>>();
+    let name = if name.is_empty() {
+        bsd_info
+            .pbi_comm
+            .iter()
+            .copied()
+            .take_while(|ch| *ch != 0)
+            .collect::<Vec<_>>()
+    } else {
+        name
+    };
+    OsStr::from_bytes(&name).to_owned()
+}
+
+// The arguments come after the count of arguments and the path of the
+// executable, which is padded out with null bytes.
+fn process_args(pid: pid_t) -> Vec<OsString> {
+    let mut arg_max: c_int = 0;
+    let mut size = std::mem::size_of::<c_int>();
+    let mut mib = [libc::CTL_KERN, libc::KERN_ARGMAX];
+    if unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            2,
+            std::ptr::addr_of_mut!(arg_max).cast::<c_void>(),
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    } != 0
+    {
+        return Vec::new();
+    }
+    #[allow(clippy::cast_sign_loss)]
+    let mut buffer = vec![0_u8; arg_max as usize];
+    let mut size = buffer.len();
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid];
+    if unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            3,
+            buffer.as_mut_ptr().cast::<c_void>(),
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    } != 0
+    {
+        return Vec::new();
+    }
+    buffer.truncate(size);
+    let argc_size = std::mem::size_of::<c_int>();
+    if buffer.len() < argc_size {
+        return Vec::new();
+    }
+    let mut argc = [0; 4];
+    argc.copy_from_slice(&buffer[..argc_size]);
+    #[allow(clippy::cast_sign_loss)]
+    let argc = c_int::from_ne_bytes(argc) as usize;
+    let args = &buffer[argc_size..];
+    let args = args
+        .iter()
+        .position(|byte| *byte == 0)
+        .map_or(&args[args.len()..], |exec_path_end| &args[exec_path_end..]);
+    let args = args
+        .iter()
+        .position(|byte| *byte != 0)
+        .map_or(&args[args.len()..], |args_start| &args[args_start..]);
+    args.split(|byte| *byte == 0)
+        .take(argc)
+        .map(|arg| OsStr::from_bytes(arg).to_owned())
+        .collect()
+}
+
+fn process_bsd_info(pid: pid_t) -> Option<proc_bsdinfo> {
+    let mut bsd_info = proc_bsdinfo::default();
+    let bsd_info_ptr: *mut proc_bsdinfo = &mut bsd_info;
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    let bsd_info_size = std::mem::size_of::<proc_bsdinfo>() as c_int;
+    if unsafe {
+        proc_pidinfo(
+            pid,
+            PROC_PIDTBSDINFO,
+            0,
+            bsd_info_ptr.cast::<c_void>(),
+            bsd_info_size,
+        )
+    } == bsd_info_size
+    {
+        Some(bsd_info)
+    } else {
+        None
+    }
 }
 
 fn fd_socket_info(
@@ -393,33 +571,141 @@ fn fd_socket_info(
     }
 }
 
-fn tcp_server_port<T: Borrow<socket_fdinfo>>(socket_info: T) -> Option<u16> {
+fn inet_socket_address(
+    family: c_int,
+    ina_46: in4in6_addr,
+    ina_6: in6_addr,
+    port: c_int,
+) -> Option<SocketAddr> {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let port = (port as u16).to_be();
+    match family {
+        AF_INET => Some(SocketAddr::from((
+            Ipv4Addr::from(u32::from_be(ina_46.i46a_addr4.s_addr)),
+            port,
+        ))),
+        AF_INET6 => Some(SocketAddr::from((
+            Ipv6Addr::from(unsafe { ina_6.__u6_addr.__u6_addr8 }),
+            port,
+        ))),
+        _ => None,
+    }
+}
+
+fn tcp_state(state: c_int) -> TcpState {
+    match state {
+        0 => TcpState::Closed,
+        1 => TcpState::Listen,
+        2 => TcpState::SynSent,
+        3 => TcpState::SynReceived,
+        4 => TcpState::Established,
+        5 => TcpState::CloseWait,
+        6 => TcpState::FinWait1,
+        7 => TcpState::Closing,
+        8 => TcpState::LastAck,
+        9 => TcpState::FinWait2,
+        10 => TcpState::TimeWait,
+        _ => TcpState::Unknown,
+    }
+}
+
+fn tcp_server_address<T: Borrow<socket_fdinfo>>(
+    socket_info: T
+) -> Option<SocketAddr> {
     let socket_info = socket_info.borrow();
-    if (socket_info.psi.soi_family == AF_INET)
-        && (socket_info.psi.soi_kind == SOCKINFO_TCP)
-        && unsafe {
-            socket_info.psi.soi_proto.pri_tcp.tcpsi_ini.insi_fport == 0
-        }
+    if socket_info.psi.soi_kind != SOCKINFO_TCP {
+        return None;
+    }
+    let inet_info = unsafe { socket_info.psi.soi_proto.pri_tcp.tcpsi_ini };
+    if inet_info.insi_fport != 0 {
+        return None;
+    }
+    inet_socket_address(
+        socket_info.psi.soi_family,
+        unsafe { inet_info.insi_laddr.ina_46 },
+        unsafe { inet_info.insi_laddr.ina_6 },
+        inet_info.insi_lport,
+    )
+}
+
+fn tcp_connection<T: Borrow<socket_fdinfo>>(
+    socket_info: T
+) -> Option<TcpConnection> {
+    let socket_info = socket_info.borrow();
+    if socket_info.psi.soi_kind != SOCKINFO_TCP {
+        return None;
+    }
+    let tcp_info = unsafe { socket_info.psi.soi_proto.pri_tcp };
+    let inet_info = tcp_info.tcpsi_ini;
+    if inet_info.insi_fport == 0 {
+        return None;
+    }
+    Some(TcpConnection {
+        local_address: inet_socket_address(
+            socket_info.psi.soi_family,
+            unsafe { inet_info.insi_laddr.ina_46 },
+            unsafe { inet_info.insi_laddr.ina_6 },
+            inet_info.insi_lport,
+        )?,
+        remote_address: inet_socket_address(
+            socket_info.psi.soi_family,
+            unsafe { inet_info.insi_faddr.ina_46 },
+            unsafe { inet_info.insi_faddr.ina_6 },
+            inet_info.insi_fport,
+        )?,
+        state: tcp_state(tcp_info.tcpsi_state),
+    })
+}
+
+fn udp_port<T: Borrow<socket_fdinfo>>(socket_info: T) -> Option<u16> {
+    let socket_info = socket_info.borrow();
+    if ((socket_info.psi.soi_family == AF_INET)
+        || (socket_info.psi.soi_family == AF_INET6))
+        && (socket_info.psi.soi_kind == SOCKINFO_IN)
+        && (socket_info.psi.soi_protocol == IPPROTO_UDP)
     {
         #[allow(clippy::cast_possible_truncation)]
         #[allow(clippy::cast_sign_loss)]
         Some(
-            unsafe {
-                socket_info.psi.soi_proto.pri_tcp.tcpsi_ini.insi_lport as u16
-            }
-            .to_be(),
+            unsafe { socket_info.psi.soi_proto.pri_in.insi_lport as u16 }
+                .to_be(),
         )
+        .filter(|port| *port != 0)
     } else {
         None
     }
 }
 
-fn process_tcp_server_ports(pid: pid_t) -> HashSet<u16> {
+fn unix_socket_listener<T: Borrow<socket_fdinfo>>(
+    socket_info: T
+) -> Option<UnixSocketName> {
+    let socket_info = socket_info.borrow();
+    if (socket_info.psi.soi_kind != SOCKINFO_UN)
+        || (((socket_info.psi.soi_options & SO_ACCEPTCONN) == 0)
+            && (socket_info.psi.soi_type != SOCK_DGRAM))
+    {
+        return None;
+    }
+    let sun_path =
+        unsafe { socket_info.psi.soi_proto.pri_un.unsi_addr.ua_sun.sun_path };
+    let path_length =
+        sun_path.iter().position(|ch| *ch == 0).unwrap_or(sun_path.len());
+    if path_length == 0 {
+        None
+    } else {
+        Some(UnixSocketName::Path(PathBuf::from(OsStr::from_bytes(
+            &sun_path[..path_length],
+        ))))
+    }
+}
+
+fn process_socket_infos(pid: pid_t) -> Vec<socket_fdinfo> {
     let buffer_size = unsafe {
         proc_pidinfo(pid, PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0)
     };
     if buffer_size < 0 {
-        return HashSet::default();
+        return Vec::default();
     }
     #[allow(clippy::cast_sign_loss)]
     let mut fds = vec![
@@ -440,23 +726,135 @@ fn process_tcp_server_ports(pid: pid_t) -> HashSet<u16> {
     fds.into_iter()
         .filter(|fd| fd.proc_fdtype == PROX_FDTYPE_SOCKET)
         .filter_map(|fd| fd_socket_info(pid, fd))
-        .filter_map(tcp_server_port)
         .collect()
 }
 
+#[allow(clippy::similar_names)]
+fn process_info(
+    pid: pid_t,
+    socket_infos: &[socket_fdinfo],
+) -> ProcessInfo {
+    #[allow(clippy::cast_sign_loss)]
+    let id = pid as usize;
+    let tcp_server_addresses = socket_infos
+        .iter()
+        .filter_map(tcp_server_address)
+        .collect::<HashSet<_>>();
+    let (tcp_server_ports, tcp6_server_ports) =
+        tcp_server_ports(&tcp_server_addresses);
+    let bsd_info = process_bsd_info(pid);
+    ProcessInfo {
+        id,
+        parent_id: bsd_info
+            .map(|bsd_info| bsd_info.pbi_ppid as usize)
+            .filter(|parent_id| *parent_id != 0),
+        start_time: bsd_info.as_ref().map(process_start_time),
+        name: bsd_info.as_ref().map(process_name).unwrap_or_default(),
+        image: process_image(pid),
+        args: process_args(pid),
+        tcp_server_addresses,
+        tcp_server_ports,
+        tcp6_server_ports,
+        tcp_connections: socket_infos
+            .iter()
+            .filter_map(tcp_connection)
+            .collect(),
+        udp_ports: socket_infos.iter().filter_map(udp_port).collect(),
+        unix_socket_listeners: socket_infos
+            .iter()
+            .filter_map(unix_socket_listener)
+            .collect(),
+    }
+}
+
 pub fn list_processes_internal() -> impl Iterator<Item = ProcessInfo> {
-    list_process_ids().into_iter().map(|pid| {
-        #[allow(clippy::cast_sign_loss)]
-        let id = pid as usize;
-        ProcessInfo {
-            id,
-            image: process_image(pid),
-            tcp_server_ports: process_tcp_server_ports(pid),
+    list_process_ids()
+        .into_iter()
+        .map(|pid| process_info(pid, &process_socket_infos(pid)))
+}
+
+pub fn process_info_internal(pid: usize) -> Option<ProcessInfo> {
+    let pid = to_pid_t(pid)?;
+    // A process we may not signal still exists, so count EPERM as found.
+    if unsafe { libc::kill(pid, 0) } != 0
+        && std::io::Error::last_os_error().raw_os_error() != Some(libc::EPERM)
+    {
+        return None;
+    }
+    Some(process_info(pid, &process_socket_infos(pid)))
+}
+
+pub fn process_listening_on_internal(port: u16) -> Vec<ProcessInfo> {
+    list_process_ids()
+        .into_iter()
+        .filter_map(|pid| {
+            let socket_infos = process_socket_infos(pid);
+            if socket_infos
+                .iter()
+                .filter_map(tcp_server_address)
+                .any(|address| address.port() == port)
+            {
+                Some(process_info(pid, &socket_infos))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// There is no way to refer to one particular process other than by its
+// identifier, so this cannot guard against the identifier being reused.
+pub struct ProcessHandleInternal {
+    pid: usize,
+}
+
+impl ProcessHandleInternal {
+    pub fn open(pid: usize) -> Result<Self, Error> {
+        if is_running_internal(pid) {
+            Ok(Self::from_pid(pid))
+        } else {
+            Err(Error::NoSuchProcess)
         }
-    })
+    }
+
+    pub fn from_pid(pid: usize) -> Self {
+        Self {
+            pid,
+        }
+    }
+
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    pub fn send_signal(
+        &self,
+        signal: Signal,
+    ) -> Result<(), Error> {
+        send_signal_internal(self.pid, signal)
+    }
+
+    pub fn wait_for_exit(
+        &self,
+        timeout: Duration,
+    ) -> bool {
+        poll_for_exit(self.pid, timeout)
+    }
+}
+
+pub fn wait_for_exit_internal(
+    pid: usize,
+    timeout: Duration,
+) -> bool {
+    poll_for_exit(pid, timeout)
+}
+
+pub fn is_zombie_internal(pid: usize) -> bool {
+    let bsd_info = to_pid_t(pid).and_then(process_bsd_info);
+    bsd_info.map(|bsd_info| bsd_info.pbi_status) == Some(SZOMB)
 }
 
-pub fn close_all_files_except(keep_open: libc::c_int) {
+pub fn close_all_files_except(keep_open: &[libc::c_int]) {
     let pid = unsafe { libc::getpid() };
     let buffer_size = unsafe {
         proc_pidinfo(pid, PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0)
@@ -484,7 +882,7 @@ pub fn close_all_files_except(keep_open: libc::c_int) {
     #[allow(clippy::cast_sign_loss)]
     fds.truncate(buffer_size as usize / std::mem::size_of::<proc_fdinfo>());
     for fd in fds {
-        if fd.proc_fd != keep_open {
+        if !keep_open.contains(&fd.proc_fd) {
             unsafe { libc::close(fd.proc_fd) };
         }
     }
@@ -0,0 +1,59 @@
+use crate::start_time_internal;
+
+/// This identifies one particular process by combining its identifier with
+/// the time at which it started.  Unlike the identifier alone, which the
+/// operating system may give to a new process once the old one exits, this
+/// stays unique across snapshots of the processes.
+///
+/// ```rust
+/// # use rhymuproc::{is_same_process, ProcessId};
+/// let self_id = ProcessId::of(std::process::id() as usize).unwrap();
+/// assert!(is_same_process(self_id));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ProcessId {
+    pid: usize,
+    start_time: u64,
+}
+
+impl ProcessId {
+    /// Rebuild the identity of a process from its `pid` (process identifier)
+    /// and `start_time`, such as those recorded earlier in a pid file, so
+    /// that [`is_same_process`] can tell whether it is still running.
+    ///
+    /// [`is_same_process`]: fn.is_same_process.html
+    #[must_use]
+    pub fn new(
+        pid: usize,
+        start_time: u64,
+    ) -> Self {
+        Self {
+            pid,
+            start_time,
+        }
+    }
+
+    /// Look up the process currently running with the given `pid` (process
+    /// identifier), returning `None` if there is no such process or its
+    /// start time cannot be read.
+    #[must_use]
+    pub fn of(pid: usize) -> Option<Self> {
+        start_time_internal(pid).map(|start_time| Self::new(pid, start_time))
+    }
+
+    /// Return the identifier of the process.
+    #[must_use]
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    /// Return the time at which the process started.  This is only
+    /// meaningful for comparing with the start times of other processes on
+    /// the same host, since its units and epoch depend on the operating
+    /// system: clock ticks since boot on Linux, microseconds since the UNIX
+    /// epoch on macOS, and 100-nanosecond intervals since 1601 on Windows.
+    #[must_use]
+    pub fn start_time(&self) -> u64 {
+        self.start_time
+    }
+}
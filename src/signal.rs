@@ -0,0 +1,41 @@
+/// This identifies a signal which can be sent to a process using
+/// [`send_signal`].  The signals are named after their UNIX counterparts.
+/// On Windows, which has no signals, only [`Kill`] and [`Terminate`] are
+/// supported, and both terminate the process immediately.
+///
+/// [`send_signal`]: fn.send_signal.html
+/// [`Kill`]: #variant.Kill
+/// [`Terminate`]: #variant.Terminate
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Signal {
+    /// This is `SIGHUP`, conventionally used to ask a daemon to reload its
+    /// configuration.
+    Hangup,
+
+    /// This is `SIGINT`, sent when the user interrupts a process from its
+    /// terminal.
+    Interrupt,
+
+    /// This is `SIGQUIT`, which asks a process to quit and dump core.
+    Quit,
+
+    /// This is `SIGKILL`, which terminates the process immediately and
+    /// cannot be caught or ignored.
+    Kill,
+
+    /// This is `SIGUSR1`, which has no predefined meaning.
+    User1,
+
+    /// This is `SIGUSR2`, which has no predefined meaning.
+    User2,
+
+    /// This is `SIGTERM`, which asks a process to shut down cleanly.
+    Terminate,
+
+    /// This is `SIGCONT`, which resumes a stopped process.
+    Continue,
+
+    /// This is `SIGSTOP`, which stops (suspends) the process, and cannot be
+    /// caught or ignored.
+    Stop,
+}
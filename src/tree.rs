@@ -0,0 +1,150 @@
+use crate::ProcessInfo;
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
+    iter::FromIterator,
+};
+
+/// This arranges a snapshot of processes, such as one returned by
+/// [`list_processes`], by their parent/child relationships.
+///
+/// ```rust
+/// # use rhymuproc::{list_processes, ProcessTree};
+/// let tree = list_processes().collect::<ProcessTree>();
+/// let self_id = std::process::id() as usize;
+/// let parent = tree.parent(self_id).unwrap();
+/// assert!(tree.children(parent.id).any(|child| child.id == self_id));
+/// ```
+///
+/// [`list_processes`]: fn.list_processes.html
+pub struct ProcessTree {
+    processes: HashMap<usize, ProcessInfo>,
+    children: HashMap<usize, Vec<usize>>,
+}
+
+impl ProcessTree {
+    /// Return the process in the tree with the given `pid` (process
+    /// identifier), if any.
+    #[must_use]
+    pub fn get(
+        &self,
+        pid: usize,
+    ) -> Option<&ProcessInfo> {
+        self.processes.get(&pid)
+    }
+
+    /// Iterate all the processes in the tree, in no particular order.
+    pub fn processes(&self) -> impl Iterator<Item = &ProcessInfo> {
+        self.processes.values()
+    }
+
+    /// Iterate the processes in the tree whose parent is not also in the
+    /// tree.
+    pub fn roots(&self) -> impl Iterator<Item = &ProcessInfo> {
+        self.processes.values().filter(move |process| {
+            process
+                .parent_id
+                .and_then(|parent_id| self.processes.get(&parent_id))
+                .is_none()
+        })
+    }
+
+    /// Return the parent of the process with the given `pid`, if both are in
+    /// the tree.
+    #[must_use]
+    pub fn parent(
+        &self,
+        pid: usize,
+    ) -> Option<&ProcessInfo> {
+        self.processes
+            .get(&pid)
+            .and_then(|process| process.parent_id)
+            .and_then(|parent_id| self.processes.get(&parent_id))
+    }
+
+    /// Iterate the processes in the tree which are direct children of the
+    /// process with the given `pid`.
+    pub fn children(
+        &self,
+        pid: usize,
+    ) -> impl Iterator<Item = &ProcessInfo> {
+        self.children
+            .get(&pid)
+            .into_iter()
+            .flatten()
+            .filter_map(move |child_id| self.processes.get(child_id))
+    }
+
+    /// Return the chain of ancestors of the process with the given `pid`,
+    /// starting with its parent and ending with the first ancestor whose own
+    /// parent is not in the tree.
+    #[must_use]
+    pub fn ancestors(
+        &self,
+        pid: usize,
+    ) -> Vec<&ProcessInfo> {
+        // Process identifiers can be reused, so guard against the snapshot
+        // describing a loop.
+        let mut visited = HashSet::new();
+        visited.insert(pid);
+        let mut ancestors = Vec::new();
+        let mut next = self.parent(pid);
+        while let Some(ancestor) = next {
+            if !visited.insert(ancestor.id) {
+                break;
+            }
+            ancestors.push(ancestor);
+            next = self.parent(ancestor.id);
+        }
+        ancestors
+    }
+
+    /// Return all the processes in the tree descended from the process with
+    /// the given `pid`.  They are listed breadth-first, so every process comes
+    /// after its parent.
+    #[must_use]
+    pub fn descendants(
+        &self,
+        pid: usize,
+    ) -> Vec<&ProcessInfo> {
+        let mut visited = HashSet::new();
+        visited.insert(pid);
+        let mut descendants = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(pid);
+        while let Some(pid) = queue.pop_front() {
+            for child in self.children(pid) {
+                if visited.insert(child.id) {
+                    descendants.push(child);
+                    queue.push_back(child.id);
+                }
+            }
+        }
+        descendants
+    }
+}
+
+impl FromIterator<ProcessInfo> for ProcessTree {
+    fn from_iter<I: IntoIterator<Item = ProcessInfo>>(processes: I) -> Self {
+        let processes = processes
+            .into_iter()
+            .map(|process| (process.id, process))
+            .collect::<HashMap<_, _>>();
+        let mut children = HashMap::new();
+        for process in processes.values() {
+            if let Some(parent_id) = process.parent_id {
+                children
+                    .entry(parent_id)
+                    .or_insert_with(Vec::new)
+                    .push(process.id);
+            }
+        }
+        Self {
+            processes,
+            children,
+        }
+    }
+}
@@ -1,106 +1,649 @@
-use crate::close_all_files_except;
+use crate::{
+    close_all_files_except,
+    is_zombie_internal,
+    DetachedCommand,
+    Error,
+    ProcessHandleInternal,
+    Signal,
+};
 use std::{
+    collections::HashSet,
+    convert::TryFrom as _,
     ffi::{
         CString,
-        OsStr,
+        OsString,
     },
+    io,
     iter::once,
-    os::unix::ffi::OsStrExt as _,
-    path::Path,
+    os::unix::{
+        ffi::{
+            OsStrExt as _,
+            OsStringExt as _,
+        },
+        io::{
+            AsRawFd as _,
+            FromRawFd as _,
+            IntoRawFd as _,
+            OwnedFd,
+        },
+    },
 };
 
-#[allow(clippy::similar_names)]
-pub fn start_detached_internal<P, A, S>(
-    path: P,
-    args: A,
-) -> usize
-where
-    P: AsRef<Path>,
-    A: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
+// The pipe ends are closed on exec, so that a process started at the same
+// time by another thread of the caller cannot hold them open.
+#[cfg(target_os = "linux")]
+fn create_pipe() -> Result<[libc::c_int; 2], Error> {
+    let mut pipe_ends = [0; 2];
+    if unsafe { libc::pipe2(pipe_ends.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(Error::CreatePipe(io::Error::last_os_error()));
+    }
+    Ok(pipe_ends)
+}
+
+// Without `pipe2` there is a short window before the pipe ends are marked to
+// be closed on exec.
+#[cfg(not(target_os = "linux"))]
+fn create_pipe() -> Result<[libc::c_int; 2], Error> {
     let mut pipe_ends = [0; 2];
     if unsafe { libc::pipe(pipe_ends.as_mut_ptr()) } < 0 {
-        return 0;
+        return Err(Error::CreatePipe(io::Error::last_os_error()));
+    }
+    for fd in pipe_ends {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    Ok(pipe_ends)
+}
+
+fn close_pipe(pipe_ends: [libc::c_int; 2]) {
+    unsafe {
+        libc::close(pipe_ends[0]);
+        libc::close(pipe_ends[1]);
+    }
+}
+
+fn write_report(
+    fd: libc::c_int,
+    report: libc::c_int,
+) {
+    unsafe {
+        libc::write(
+            fd,
+            std::ptr::addr_of!(report).cast::<libc::c_void>(),
+            std::mem::size_of::<libc::c_int>(),
+        );
     }
-    let child_args = once(path.as_ref().to_path_buf().into_os_string())
-        .chain(args.into_iter().map(|arg| arg.as_ref().to_owned()))
-        .map(|arg| CString::new(arg.as_bytes()).unwrap())
+}
+
+// This returns `None` if the other end of the pipe was closed without a
+// complete report being written.
+fn read_report(fd: libc::c_int) -> Option<libc::c_int> {
+    let mut report: libc::c_int = 0;
+    let read_amount = unsafe {
+        libc::read(
+            fd,
+            std::ptr::addr_of_mut!(report).cast::<libc::c_void>(),
+            std::mem::size_of::<libc::c_int>(),
+        )
+    };
+    #[allow(clippy::cast_sign_loss)]
+    if read_amount as usize == std::mem::size_of::<libc::c_int>() {
+        Some(report)
+    } else {
+        None
+    }
+}
+
+fn null_terminated(strings: &[CString]) -> Vec<*const libc::c_char> {
+    strings
+        .iter()
+        .map(|string| string.as_ptr())
+        .chain(once(std::ptr::null()))
+        .collect()
+}
+
+fn last_error_code() -> libc::c_int {
+    io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+// These identify the step of setting up the detached process which failed,
+// when it reports an error code through the exec pipe.
+const STEP_EXEC: libc::c_int = 0;
+const STEP_CURRENT_DIR: libc::c_int = 1;
+const STEP_STDIO: libc::c_int = 2;
+const STEP_PASS_FD: libc::c_int = 3;
+const STEP_SET_GROUPS: libc::c_int = 4;
+const STEP_SET_GID: libc::c_int = 5;
+const STEP_SET_UID: libc::c_int = 6;
+
+// Room is left after the name of this environment variable for the detached
+// process to fill in its own identifier, which only it knows.
+const LISTEN_PID_PREFIX: &[u8] = b"LISTEN_PID=";
+const LISTEN_PID_DIGITS: usize = 20;
+
+fn setup_error(
+    step: libc::c_int,
+    error_code: libc::c_int,
+) -> Error {
+    let error = io::Error::from_raw_os_error(error_code);
+    match step {
+        STEP_CURRENT_DIR => Error::CurrentDir(error),
+        STEP_STDIO => Error::Stdio(error),
+        STEP_PASS_FD => Error::PassFd(error),
+        STEP_SET_GROUPS => Error::SetGroups(error),
+        STEP_SET_GID => Error::SetGid(error),
+        STEP_SET_UID => Error::SetUid(error),
+        _ => Error::Exec(error),
+    }
+}
+
+// Each file to give the detached process is first duplicated to a number
+// above all of those it is to be given, so that moving one into place cannot
+// replace another which is still to be moved.
+fn raise_fd(
+    fd: libc::c_int,
+    floor: libc::c_int,
+) -> io::Result<OwnedFd> {
+    let raised_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, floor) };
+    if raised_fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(raised_fd) })
+    }
+}
+
+// The standard input and output come first, followed by the passed file
+// descriptors, each paired with the number to give it in the detached
+// process.  The number above all of those is also returned.  A passed file
+// descriptor may not take the place of the standard input and output, nor of
+// another one, since only the last given would be kept.
+fn prepare_files(
+    command: &DetachedCommand
+) -> Result<(Vec<(OwnedFd, libc::c_int)>, libc::c_int), Error> {
+    let mut targets = HashSet::new();
+    if command
+        .passed_fds
+        .iter()
+        .any(|(_, target)| (0..3).contains(target) || !targets.insert(*target))
+    {
+        return Err(Error::PassFd(io::Error::from_raw_os_error(libc::EINVAL)));
+    }
+    let fd_floor = command
+        .passed_fds
+        .iter()
+        .map(|(_, target)| target + 1)
+        .fold(3, libc::c_int::max);
+    let mut files = Vec::new();
+    for (file, target) in command.open_stdio()?.iter().zip(0..) {
+        let file =
+            raise_fd(file.as_raw_fd(), fd_floor).map_err(Error::Stdio)?;
+        files.push((file, target));
+    }
+    for (fd, target) in &command.passed_fds {
+        let file = raise_fd(*fd, fd_floor).map_err(Error::PassFd)?;
+        files.push((file, *target));
+    }
+    Ok((files, fd_floor))
+}
+
+// A null character would cut short the string seen by the operating system,
+// so is reported rather than passed along.
+fn c_string<T: Into<Vec<u8>>>(bytes: T) -> Result<CString, Error> {
+    CString::new(bytes).map_err(|_| Error::InvalidInput)
+}
+
+// This looks up the primary group of the named user, which also tells whether
+// there is such a user, as `getgrouplist` does not.
+fn find_user(user: &CString) -> Result<libc::gid_t, Error> {
+    let mut buffer = vec![0_u8; 1024];
+    loop {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let error_code = unsafe {
+            libc::getpwnam_r(
+                user.as_ptr(),
+                std::ptr::addr_of_mut!(passwd),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                std::ptr::addr_of_mut!(found),
+            )
+        };
+        match error_code {
+            0 if found.is_null() => {
+                return Err(Error::SetGroups(io::Error::from_raw_os_error(
+                    libc::ENOENT,
+                )))
+            },
+            0 => return Ok(passwd.pw_gid),
+            libc::ERANGE => buffer.resize(buffer.len() * 2, 0),
+            error_code => {
+                return Err(Error::SetGroups(io::Error::from_raw_os_error(
+                    error_code,
+                )))
+            },
+        }
+    }
+}
+
+// The groups of a user are looked up beforehand, rather than by calling
+// `initgroups` in the detached process, since the lookup may allocate memory
+// or take locks.  A process run as another user without any groups given
+// has none, rather than keeping those of the caller.  The primary group of
+// the user is also returned, as the one to set unless another is given.
+fn prepare_groups(
+    command: &DetachedCommand
+) -> Result<(Option<libc::gid_t>, Option<Vec<libc::gid_t>>), Error> {
+    let Some(user) = &command.init_groups_user else {
+        let other_user =
+            command.uid.is_some_and(|uid| uid != unsafe { libc::geteuid() });
+        return Ok((
+            command.gid,
+            command.groups.clone().or_else(|| other_user.then(Vec::new)),
+        ));
+    };
+    let user = c_string(user.as_bytes())?;
+    let user_gid = find_user(&user)?;
+    let gid = command.gid.unwrap_or(user_gid);
+    let mut user_groups: Vec<libc::gid_t> = vec![0; 32];
+    loop {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_possible_wrap)]
+        let mut count = user_groups.len() as libc::c_int;
+        let result = unsafe {
+            libc::getgrouplist(
+                user.as_ptr(),
+                gid as _,
+                user_groups.as_mut_ptr().cast(),
+                std::ptr::addr_of_mut!(count),
+            )
+        };
+        if result >= 0 {
+            #[allow(clippy::cast_sign_loss)]
+            user_groups.truncate(count as usize);
+            break;
+        }
+        #[allow(clippy::cast_sign_loss)]
+        let count = (count as usize).max(user_groups.len() * 2);
+        user_groups.resize(count, 0);
+    }
+    let mut groups = command.groups.clone().unwrap_or_default();
+    groups.extend(user_groups);
+    Ok((Some(gid), Some(groups)))
+}
+
+// This writes the decimal digits of the identifier of the calling process,
+// followed by a null terminator, without allocating memory.
+fn write_pid(digits: *mut u8) {
+    #[allow(clippy::cast_sign_loss)]
+    let mut pid = unsafe { libc::getpid() } as u32;
+    let mut reversed = [0_u8; LISTEN_PID_DIGITS];
+    let mut length = 0;
+    loop {
+        #[allow(clippy::cast_possible_truncation)]
+        let digit = (pid % 10) as u8;
+        reversed[length] = b'0' + digit;
+        length += 1;
+        pid /= 10;
+        if pid == 0 {
+            break;
+        }
+    }
+    for (i, digit) in reversed[..length].iter().rev().enumerate() {
+        unsafe { *digits.add(i) = *digit };
+    }
+    unsafe { *digits.add(length) = 0 };
+}
+
+// Everything the forked processes need is prepared beforehand, so that they
+// allocate as little memory as possible, since another thread of the caller
+// may have held a lock of the allocator when it forked.  Closing the files
+// they are not to inherit still allocates, as they are listed first.
+struct PreparedCommand {
+    program: CString,
+    files: Vec<(OwnedFd, libc::c_int)>,
+    fd_floor: libc::c_int,
+    current_dir: Option<CString>,
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+    groups: Option<Vec<libc::gid_t>>,
+    _args: Vec<CString>,
+    arg_pointers: Vec<*const libc::c_char>,
+    _env: Option<Vec<CString>>,
+    listen_pid: Option<Vec<u8>>,
+    listen_pid_digits: *mut u8,
+    env_pointers: Option<Vec<*const libc::c_char>>,
+}
+
+impl PreparedCommand {
+    fn new(command: &DetachedCommand) -> Result<Self, Error> {
+        let program = c_string(command.program.as_os_str().as_bytes())?;
+        let (files, fd_floor) = prepare_files(command)?;
+        let (gid, groups) = prepare_groups(command)?;
+        let current_dir = command
+            .current_dir
+            .as_ref()
+            .map(|dir| c_string(dir.as_os_str().as_bytes()))
+            .transpose()?;
+        let args = once(command.program.as_os_str())
+            .chain(command.args.iter().map(OsString::as_os_str))
+            .map(|arg| c_string(arg.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let arg_pointers = null_terminated(&args);
+        let env = command
+            .environment()
+            .map(|environment| {
+                environment
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let mut variable = key.into_vec();
+                        variable.push(b'=');
+                        variable.extend(value.into_vec());
+                        c_string(variable)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        let mut env_pointers = env.as_deref().map(null_terminated);
+        let mut listen_pid = None;
+        let mut listen_pid_digits = std::ptr::null_mut();
+        if command.listen_fd_count > 0 {
+            let mut variable = LISTEN_PID_PREFIX.to_vec();
+            variable.resize(LISTEN_PID_PREFIX.len() + LISTEN_PID_DIGITS + 1, 0);
+            let variable = listen_pid.insert(variable).as_mut_ptr();
+            listen_pid_digits =
+                unsafe { variable.add(LISTEN_PID_PREFIX.len()) };
+            if let Some(env_pointers) = &mut env_pointers {
+                env_pointers.insert(env_pointers.len() - 1, variable.cast());
+            }
+        }
+        Ok(Self {
+            program,
+            files,
+            fd_floor,
+            current_dir,
+            uid: command.uid,
+            gid,
+            groups,
+            _args: args,
+            arg_pointers,
+            _env: env,
+            listen_pid,
+            listen_pid_digits,
+            env_pointers,
+        })
+    }
+
+    // This returns the descriptors of the files which the intermediate
+    // process must keep open to give to the detached process.
+    fn kept_fds(&self) -> impl Iterator<Item = libc::c_int> + '_ {
+        self.files.iter().map(|(file, _)| file.as_raw_fd())
+    }
+
+    // This runs in the detached process, and only returns if setting it up
+    // or the exec fails, with the step which failed.
+    fn exec(&self) -> libc::c_int {
+        for (i, (file, fd)) in self.files.iter().enumerate() {
+            if unsafe { libc::dup2(file.as_raw_fd(), *fd) } < 0 {
+                return if i < 3 {
+                    STEP_STDIO
+                } else {
+                    STEP_PASS_FD
+                };
+            }
+        }
+        if let Some(groups) = &self.groups {
+            if unsafe { libc::setgroups(groups.len() as _, groups.as_ptr()) }
+                < 0
+            {
+                return STEP_SET_GROUPS;
+            }
+        }
+        if let Some(gid) = self.gid {
+            if unsafe { libc::setgid(gid) } < 0 {
+                return STEP_SET_GID;
+            }
+        }
+        if let Some(uid) = self.uid {
+            if unsafe { libc::setuid(uid) } < 0 {
+                return STEP_SET_UID;
+            }
+        }
+        if self.listen_pid.is_some() {
+            write_pid(self.listen_pid_digits);
+        }
+        if let Some(current_dir) = &self.current_dir {
+            if unsafe { libc::chdir(current_dir.as_ptr()) } < 0 {
+                return STEP_CURRENT_DIR;
+            }
+        }
+        unsafe {
+            match &self.env_pointers {
+                Some(env_pointers) => libc::execve(
+                    self.program.as_ptr(),
+                    self.arg_pointers.as_ptr(),
+                    env_pointers.as_ptr(),
+                ),
+                None => libc::execv(
+                    self.program.as_ptr(),
+                    self.arg_pointers.as_ptr(),
+                ),
+            };
+        }
+        STEP_EXEC
+    }
+}
+
+// This runs in the intermediate process, which starts the detached process in
+// a new session, reports it, and waits to be released.  The detached process
+// leads a process group of its own, so that its tree can be found through the
+// group once the intermediate process, which leads the session, has exited.
+// Both exit with `_exit`, so as not to run the exit handlers of the caller or
+// flush its buffered output a second time.
+fn start_grandchild(
+    prepared: &PreparedCommand,
+    keep_open: &[libc::c_int],
+    pid_pipe: [libc::c_int; 2],
+    exec_pipe: [libc::c_int; 2],
+    release_pipe: [libc::c_int; 2],
+) -> ! {
+    close_all_files_except(keep_open);
+    unsafe { libc::setsid() };
+    let grandchild = unsafe { libc::fork() };
+    if grandchild == 0 {
+        unsafe {
+            libc::setpgid(0, 0);
+            libc::close(pid_pipe[1]);
+            libc::close(release_pipe[0]);
+        }
+        // The exec pipe is moved out of the way of the files given to the
+        // detached process, as they are.
+        let (step, report_fd) = match raise_fd(exec_pipe[1], prepared.fd_floor)
+        {
+            Ok(report_fd) => {
+                unsafe { libc::close(exec_pipe[1]) };
+                (prepared.exec(), report_fd.into_raw_fd())
+            },
+            Err(_) => (STEP_STDIO, exec_pipe[1]),
+        };
+        let error_code = last_error_code();
+        write_report(report_fd, step);
+        write_report(report_fd, error_code);
+        unsafe { libc::_exit(-1) };
+    }
+    let report = if grandchild < 0 {
+        -last_error_code()
+    } else {
+        grandchild
+    };
+    write_report(pid_pipe[1], report);
+    let mut released = 0_u8;
+    unsafe {
+        libc::read(
+            release_pipe[0],
+            std::ptr::addr_of_mut!(released).cast::<libc::c_void>(),
+            1,
+        );
+        libc::_exit(0);
+    }
+}
+
+// The detached process is started by an intermediate process, which reports
+// through one pipe either the identifier of the detached process, or the
+// negated error code if it could not be forked.  The detached process holds
+// the write end of a second pipe, marked to be closed on exec, through which
+// it reports the step which failed and the error code if it cannot be set up
+// or the exec fails.  Seeing that pipe closed without a report therefore
+// means the exec succeeded.
+//
+// The intermediate process then waits for a third pipe to be closed before
+// exiting.  Until then the detached process remains its child, so cannot be
+// reaped and have its identifier reused before a handle to it is opened.
+pub fn start_detached_internal(
+    command: &DetachedCommand
+) -> Result<ProcessHandleInternal, Error> {
+    let prepared = PreparedCommand::new(command)?;
+    let pid_pipe = create_pipe()?;
+    let exec_pipe = create_pipe().inspect_err(|_| close_pipe(pid_pipe))?;
+    let release_pipe = create_pipe().inspect_err(|_| {
+        close_pipe(pid_pipe);
+        close_pipe(exec_pipe);
+    })?;
+    let keep_open = prepared
+        .kept_fds()
+        .chain([pid_pipe[1], exec_pipe[1], release_pipe[0]])
         .collect::<Vec<_>>();
     let child = unsafe { libc::fork() };
     match child {
-        0 => {
-            close_all_files_except(pipe_ends[1]);
-            unsafe { libc::setsid() };
-            let grandchild = unsafe { libc::fork() };
-            match grandchild {
-                0 => {
-                    unsafe { libc::close(pipe_ends[1]) };
-                    let program = CString::new(
-                        path.as_ref().to_path_buf().into_os_string().as_bytes(),
-                    )
-                    .unwrap();
-                    let argv = child_args
-                        .iter()
-                        .map(|arg| arg.as_ptr())
-                        .chain(once(std::ptr::null()))
-                        .collect::<Vec<_>>();
-                    unsafe {
-                        libc::execv(program.as_ptr(), argv.as_ptr());
-                        libc::exit(-1);
-                    }
-                },
-                grandchild if grandchild < 0 => {
-                    unsafe { libc::exit(-1) };
-                },
-                _ => {},
-            };
-            unsafe {
-                libc::write(
-                    pipe_ends[1],
-                    &grandchild as *const i32 as *const libc::c_void,
-                    std::mem::size_of::<libc::c_int>(),
-                );
-                libc::exit(0);
-            }
-        },
+        0 => start_grandchild(
+            &prepared,
+            &keep_open,
+            pid_pipe,
+            exec_pipe,
+            release_pipe,
+        ),
         child if child < 0 => {
-            unsafe {
-                libc::close(pipe_ends[0]);
-                libc::close(pipe_ends[1]);
-            }
-            return 0;
+            let error = io::Error::last_os_error();
+            close_pipe(pid_pipe);
+            close_pipe(exec_pipe);
+            close_pipe(release_pipe);
+            return Err(Error::Fork(error));
         },
         _ => {},
-    };
-    unsafe { libc::close(pipe_ends[1]) };
-    let mut child_status = 0;
-    unsafe { libc::waitpid(child, &mut child_status as *mut libc::c_int, 0) };
-    if libc::WEXITSTATUS(child_status) != 0 {
-        unsafe { libc::close(pipe_ends[0]) };
-        return 0;
     }
-    let mut detached_process_id: libc::c_uint = 0;
-    let read_amount = unsafe {
-        libc::read(
-            pipe_ends[0],
-            &mut detached_process_id as *mut libc::c_uint as *mut libc::c_void,
-            std::mem::size_of::<libc::c_uint>(),
-        )
-    };
-    unsafe { libc::close(pipe_ends[0]) };
+    unsafe {
+        libc::close(pid_pipe[1]);
+        libc::close(exec_pipe[1]);
+        libc::close(release_pipe[0]);
+    }
+    let pid_report = read_report(pid_pipe[0]);
+    unsafe { libc::close(pid_pipe[0]) };
+    // Opening a handle can fail, as when the caller has run out of file
+    // descriptors, but the process has been started all the same, so fall
+    // back to a handle which only knows its identifier.
     #[allow(clippy::cast_sign_loss)]
-    if read_amount as usize == std::mem::size_of::<libc::c_uint>() {
-        detached_process_id as usize
-    } else {
-        0
+    let handle = pid_report.filter(|pid| *pid > 0).map(|pid| {
+        ProcessHandleInternal::open(pid as usize)
+            .unwrap_or_else(|_| ProcessHandleInternal::from_pid(pid as usize))
+    });
+    unsafe { libc::close(release_pipe[1]) };
+    let mut child_status = 0;
+    unsafe { libc::waitpid(child, std::ptr::addr_of_mut!(child_status), 0) };
+    let exec_report = read_report(exec_pipe[0])
+        .map(|step| (step, read_report(exec_pipe[0]).unwrap_or(0)));
+    unsafe { libc::close(exec_pipe[0]) };
+    if !libc::WIFEXITED(child_status) || libc::WEXITSTATUS(child_status) != 0 {
+        return Err(Error::IntermediateProcess);
+    }
+    match (pid_report, exec_report, handle) {
+        (None, _, _) => Err(Error::IntermediateProcess),
+        (Some(pid_report), _, _) if pid_report < 0 => {
+            Err(Error::Fork(io::Error::from_raw_os_error(-pid_report)))
+        },
+        (_, Some((step, error_code)), _) => Err(setup_error(step, error_code)),
+        (_, None, Some(handle)) => Ok(handle),
+        (_, None, None) => Err(Error::IntermediateProcess),
     }
 }
 
-pub fn kill_internal(pid: usize) {
+// Only a positive identifier names a single process.  Given zero or a negative
+// one, as a larger identifier would wrap to, `kill` acts on a whole process
+// group or on every process the caller may signal.
+pub fn to_pid_t(pid: usize) -> Option<libc::pid_t> {
+    libc::pid_t::try_from(pid).ok().filter(|pid| *pid > 0)
+}
+
+// A process we may not signal is still running, so count EPERM as alive.  A
+// zombie has exited, even though it remains until its parent reaps it.
+pub fn is_running_internal(pid: usize) -> bool {
+    let Some(pid_t) = to_pid_t(pid) else {
+        return false;
+    };
+    let exists = unsafe { libc::kill(pid_t, 0) } == 0
+        || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    exists && !is_zombie_internal(pid)
+}
+
+// Processes in the process group or session led by the given process belong
+// to its tree even after being orphaned.  Those of a group or session led by
+// some other process do not, even if its leader has exited, since they may
+// include the ancestors of the given process.
+pub fn group_members_internal(
+    pid: usize,
+    candidates: &[usize],
+) -> Vec<usize> {
+    let Some(pid) = to_pid_t(pid) else {
+        return Vec::new();
+    };
     #[allow(clippy::cast_possible_truncation)]
     #[allow(clippy::cast_possible_wrap)]
-    unsafe {
-        libc::kill(pid as i32, libc::SIGKILL)
+    let ids_of = |pid: usize| unsafe {
+        let pid = pid as libc::pid_t;
+        (libc::getpgid(pid), libc::getsid(pid))
     };
+    let include_group = unsafe { libc::getpgid(pid) } == pid;
+    let include_session = unsafe { libc::getsid(pid) } == pid;
+    if !include_group && !include_session {
+        return Vec::new();
+    }
+    candidates
+        .iter()
+        .copied()
+        .filter(|candidate| {
+            let (candidate_group, candidate_session) = ids_of(*candidate);
+            (include_group && candidate_group == pid)
+                || (include_session && candidate_session == pid)
+        })
+        .collect()
+}
+
+pub fn signal_number(signal: Signal) -> libc::c_int {
+    match signal {
+        Signal::Hangup => libc::SIGHUP,
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Quit => libc::SIGQUIT,
+        Signal::Kill => libc::SIGKILL,
+        Signal::User1 => libc::SIGUSR1,
+        Signal::User2 => libc::SIGUSR2,
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Continue => libc::SIGCONT,
+        Signal::Stop => libc::SIGSTOP,
+    }
+}
+
+pub fn send_signal_internal(
+    pid: usize,
+    signal: Signal,
+) -> Result<(), Error> {
+    let pid = to_pid_t(pid).ok_or(Error::NoSuchProcess)?;
+    if unsafe { libc::kill(pid, signal_number(signal)) } == 0 {
+        return Ok(());
+    }
+    Err(signal_error(io::Error::last_os_error()))
+}
+
+pub fn signal_error(error: io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(libc::ESRCH) => Error::NoSuchProcess,
+        Some(libc::EPERM) => Error::PermissionDenied,
+        _ => Error::Kill(error),
+    }
 }
@@ -2,40 +2,78 @@
 // to be used as-is here.
 #![allow(clippy::upper_case_acronyms)]
 
-use crate::ProcessInfo;
+use crate::{
+    poll_for_exit,
+    tcp_server_ports,
+    DetachedCommand,
+    Error,
+    ProcessInfo,
+    Signal,
+    TcpConnection,
+    TcpState,
+};
 use std::{
     borrow::Cow,
     collections::{
         HashMap,
         HashSet,
     },
+    convert::TryFrom as _,
     ffi::{
         c_void,
         OsStr,
         OsString,
     },
+    io,
     iter::{
         once,
         repeat,
     },
+    net::{
+        Ipv4Addr,
+        Ipv6Addr,
+        SocketAddr,
+        SocketAddrV6,
+    },
     os::windows::{
         ffi::OsStringExt as _,
+        io::AsRawHandle as _,
         prelude::OsStrExt as _,
     },
     path::{
         Path,
         PathBuf,
     },
+    time::Duration,
 };
 
 type HANDLE = *const c_void;
 const PROCESS_QUERY_INFORMATION: u32 = 0x0400;
 const PROCESS_VM_READ: u32 = 0x0010;
 const NO_ERROR: u32 = 0;
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_INVALID_PARAMETER: i32 = 87;
 const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
 const MIB_TCP_STATE_LISTEN: u32 = 2;
+const AF_INET: u32 = 2;
+const AF_INET6: u32 = 23;
+const UDP_TABLE_OWNER_PID: u32 = 1;
 const DETACHED_PROCESS: u32 = 0x0000_0008;
+const CREATE_UNICODE_ENVIRONMENT: u32 = 0x0000_0400;
+const STARTF_USESTDHANDLES: u32 = 0x0000_0100;
+const EXTENDED_STARTUPINFO_PRESENT: u32 = 0x0008_0000;
+const PROC_THREAD_ATTRIBUTE_HANDLE_LIST: usize = 0x0002_0002;
+const HANDLE_FLAG_INHERIT: u32 = 0x0000_0001;
 const PROCESS_TERMINATE: u32 = 0x0001;
+const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+const STILL_ACTIVE: u32 = 259;
+const SYNCHRONIZE: u32 = 0x0010_0000;
+const WAIT_OBJECT_0: u32 = 0;
+const INFINITE: u32 = 0xFFFF_FFFF;
+const TH32CS_SNAPPROCESS: u32 = 0x0000_0002;
+const MAX_PATH: usize = 260;
+const PROCESS_COMMAND_LINE_INFORMATION: u32 = 60;
+const INVALID_HANDLE_VALUE: isize = -1;
 
 #[repr(C)]
 #[allow(non_snake_case)]
@@ -56,6 +94,89 @@ struct MIB_TCPTABLE2 {
     table: [MIB_TCPROW2; 1],
 }
 
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_TCP6ROW2 {
+    LocalAddr: [u8; 16],
+    dwLocalScopeId: u32,
+    dwLocalPort: u32,
+    RemoteAddr: [u8; 16],
+    dwRemoteScopeId: u32,
+    dwRemotePort: u32,
+    State: u32,
+    dwOwningPid: u32,
+    dwOffloadState: u32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_TCP6TABLE2 {
+    dwNumEntries: u32,
+    table: [MIB_TCP6ROW2; 1],
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_UDPROW_OWNER_PID {
+    dwLocalAddr: u32,
+    dwLocalPort: u32,
+    dwOwningPid: u32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_UDPTABLE_OWNER_PID {
+    dwNumEntries: u32,
+    table: [MIB_UDPROW_OWNER_PID; 1],
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_UDP6ROW_OWNER_PID {
+    ucLocalAddr: [u8; 16],
+    dwLocalScopeId: u32,
+    dwLocalPort: u32,
+    dwOwningPid: u32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct MIB_UDP6TABLE_OWNER_PID {
+    dwNumEntries: u32,
+    table: [MIB_UDP6ROW_OWNER_PID; 1],
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct PROCESSENTRY32W {
+    dwSize: u32,
+    cntUsage: u32,
+    th32ProcessID: u32,
+    th32DefaultHeapID: usize,
+    th32ModuleID: u32,
+    cntThreads: u32,
+    th32ParentProcessID: u32,
+    pcPriClassBase: i32,
+    dwFlags: u32,
+    szExeFile: [u16; MAX_PATH],
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Default)]
+struct FILETIME {
+    dwLowDateTime: u32,
+    dwHighDateTime: u32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct UNICODE_STRING {
+    Length: u16,
+    MaximumLength: u16,
+    Buffer: *const u16,
+}
+
 #[link(name = "psapi")]
 extern "C" {
     fn EnumProcesses(
@@ -73,6 +194,26 @@ extern "C" {
         dwProcessId: u32,
     ) -> HANDLE;
     fn CloseHandle(hObject: HANDLE) -> bool;
+    fn SetHandleInformation(
+        hObject: HANDLE,
+        dwMask: u32,
+        dwFlags: u32,
+    ) -> bool;
+    fn WaitForSingleObject(
+        hHandle: HANDLE,
+        dwMilliseconds: u32,
+    ) -> u32;
+    fn GetProcessTimes(
+        hProcess: HANDLE,
+        lpCreationTime: *mut FILETIME,
+        lpExitTime: *mut FILETIME,
+        lpKernelTime: *mut FILETIME,
+        lpUserTime: *mut FILETIME,
+    ) -> bool;
+    fn GetExitCodeProcess(
+        hProcess: HANDLE,
+        lpExitCode: *mut u32,
+    ) -> bool;
     fn TerminateProcess(
         hProcess: HANDLE,
         uExitCode: u32,
@@ -95,7 +236,55 @@ extern "C" {
         lpStartupInfo: *const STARTUPINFOW,
         lpProcessInformation: *mut PROCESS_INFORMATION,
     ) -> bool;
+    fn InitializeProcThreadAttributeList(
+        lpAttributeList: *mut c_void,
+        dwAttributeCount: u32,
+        dwFlags: u32,
+        lpSize: *mut usize,
+    ) -> bool;
+    fn UpdateProcThreadAttribute(
+        lpAttributeList: *mut c_void,
+        dwFlags: u32,
+        Attribute: usize,
+        lpValue: *const c_void,
+        cbSize: usize,
+        lpPreviousValue: *mut c_void,
+        lpReturnSize: *const usize,
+    ) -> bool;
+    fn DeleteProcThreadAttributeList(lpAttributeList: *mut c_void);
     fn GetLastError() -> u32;
+    fn LocalFree(hMem: *mut c_void) -> *mut c_void;
+    fn CreateToolhelp32Snapshot(
+        dwFlags: u32,
+        th32ProcessID: u32,
+    ) -> HANDLE;
+    fn Process32FirstW(
+        hSnapshot: HANDLE,
+        lppe: *mut PROCESSENTRY32W,
+    ) -> bool;
+    fn Process32NextW(
+        hSnapshot: HANDLE,
+        lppe: *mut PROCESSENTRY32W,
+    ) -> bool;
+}
+
+#[link(name = "ntdll")]
+extern "C" {
+    fn NtQueryInformationProcess(
+        ProcessHandle: HANDLE,
+        ProcessInformationClass: u32,
+        ProcessInformation: *mut c_void,
+        ProcessInformationLength: u32,
+        ReturnLength: *mut u32,
+    ) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "C" {
+    fn CommandLineToArgvW(
+        lpCmdLine: *const u16,
+        pNumArgs: *mut i32,
+    ) -> *mut *mut u16;
 }
 
 #[link(name = "Iphlpapi")]
@@ -105,6 +294,19 @@ extern "C" {
         SizePointer: *mut u32,
         Order: bool,
     ) -> u32;
+    fn GetTcp6Table2(
+        TcpTable: *mut MIB_TCP6TABLE2,
+        SizePointer: *mut u32,
+        Order: bool,
+    ) -> u32;
+    fn GetExtendedUdpTable(
+        pUdpTable: *mut c_void,
+        pdwSize: *mut u32,
+        bOrder: bool,
+        ulAf: u32,
+        TableClass: u32,
+        Reserved: u32,
+    ) -> u32;
 }
 
 #[allow(non_snake_case)]
@@ -130,6 +332,13 @@ struct STARTUPINFOW {
     hStdError: HANDLE,
 }
 
+#[allow(non_snake_case)]
+#[repr(C)]
+struct STARTUPINFOEXW {
+    StartupInfo: STARTUPINFOW,
+    lpAttributeList: *mut c_void,
+}
+
 #[allow(non_snake_case)]
 #[repr(C)]
 struct PROCESS_INFORMATION {
@@ -169,6 +378,69 @@ impl Drop for SafeHandle {
     }
 }
 
+// The attributes given to a new process, which are deleted when dropped.  The
+// buffer is made of words, since the list holds pointers.
+struct AttributeList(Vec<usize>);
+
+impl AttributeList {
+    fn new(attribute_count: u32) -> io::Result<Self> {
+        let mut size = 0;
+        unsafe {
+            InitializeProcThreadAttributeList(
+                std::ptr::null_mut(),
+                attribute_count,
+                0,
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        let mut buffer = vec![0_usize; size / std::mem::size_of::<usize>() + 1];
+        let initialized = unsafe {
+            InitializeProcThreadAttributeList(
+                buffer.as_mut_ptr().cast(),
+                attribute_count,
+                0,
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        if initialized {
+            Ok(Self(buffer))
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    // The handles are not copied, so they must outlive the list.
+    fn inherit_only(handles: &[HANDLE]) -> io::Result<Self> {
+        let mut attributes = Self::new(1)?;
+        let updated = unsafe {
+            UpdateProcThreadAttribute(
+                attributes.as_mut_ptr(),
+                0,
+                PROC_THREAD_ATTRIBUTE_HANDLE_LIST,
+                handles.as_ptr().cast(),
+                std::mem::size_of_val(handles),
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        if updated {
+            Ok(attributes)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.0.as_mut_ptr().cast()
+    }
+}
+
+impl Drop for AttributeList {
+    fn drop(&mut self) {
+        unsafe { DeleteProcThreadAttributeList(self.as_mut_ptr()) };
+    }
+}
+
 fn list_process_ids() -> Vec<u32> {
     let mut process_ids = vec![0_u32; 1024];
     loop {
@@ -192,46 +464,200 @@ fn list_process_ids() -> Vec<u32> {
     }
 }
 
-fn list_tcp_server_ports_per_process() -> HashMap<u32, HashSet<u16>> {
-    let mut tcp_server_ports = HashMap::new();
-    let mut required_tcp_table_size = 4096;
-    let mut tcp_table_buffer = vec![];
-    let get_tcp_table_result = loop {
-        tcp_table_buffer.resize(required_tcp_table_size as usize, 0);
-        let get_tcp_table_result = unsafe {
-            GetTcpTable2(
-                tcp_table_buffer.as_mut_ptr().cast::<MIB_TCPTABLE2>(),
-                &mut required_tcp_table_size,
-                false,
-            )
-        };
-        if get_tcp_table_result != ERROR_INSUFFICIENT_BUFFER {
-            break get_tcp_table_result;
+// The tables are made up of 32-bit fields, so the buffer is made of them too,
+// to keep it aligned.  Its size is still given in bytes.
+fn get_table<F>(mut get_table_into: F) -> Option<Vec<u32>>
+where
+    F: FnMut(*mut u32, &mut u32) -> u32,
+{
+    let mut required_table_size = 4096;
+    let mut table_buffer = vec![];
+    let get_table_result = loop {
+        table_buffer.resize((required_table_size as usize).div_ceil(4), 0);
+        let get_table_result =
+            get_table_into(table_buffer.as_mut_ptr(), &mut required_table_size);
+        if get_table_result != ERROR_INSUFFICIENT_BUFFER {
+            break get_table_result;
         }
     };
-    if get_tcp_table_result == NO_ERROR {
-        let tcp_table = tcp_table_buffer.as_mut_ptr() as *const MIB_TCPTABLE2;
+    if get_table_result == NO_ERROR {
+        Some(table_buffer)
+    } else {
+        None
+    }
+}
+
+fn tcp_state(state: u32) -> TcpState {
+    match state {
+        1 | 12 => TcpState::Closed,
+        MIB_TCP_STATE_LISTEN => TcpState::Listen,
+        3 => TcpState::SynSent,
+        4 => TcpState::SynReceived,
+        5 => TcpState::Established,
+        6 => TcpState::FinWait1,
+        7 => TcpState::FinWait2,
+        8 => TcpState::CloseWait,
+        9 => TcpState::Closing,
+        10 => TcpState::LastAck,
+        11 => TcpState::TimeWait,
+        _ => TcpState::Unknown,
+    }
+}
+
+fn list_tcp_sockets_per_process() -> HashMap<u32, Vec<TcpConnection>> {
+    let mut tcp_sockets = HashMap::new();
+    if let Some(tcp_table_buffer) = get_table(|buffer, size| unsafe {
+        GetTcpTable2(buffer.cast::<MIB_TCPTABLE2>(), size, false)
+    }) {
+        let tcp_table = tcp_table_buffer.as_ptr().cast::<MIB_TCPTABLE2>();
         let num_entries = unsafe { (*tcp_table).dwNumEntries };
         for i in 0..num_entries {
             let i = i as usize;
             let tcp_table_entry =
                 unsafe { (*tcp_table).table.get_unchecked(i) };
-            if tcp_table_entry.dwState == MIB_TCP_STATE_LISTEN {
-                #[allow(clippy::cast_possible_truncation)]
-                tcp_server_ports
-                    .entry(tcp_table_entry.dwOwningPid)
-                    .or_insert_with(HashSet::new)
-                    .insert((tcp_table_entry.dwLocalPort as u16).to_be());
-            }
+            #[allow(clippy::cast_possible_truncation)]
+            tcp_sockets
+                .entry(tcp_table_entry.dwOwningPid)
+                .or_insert_with(Vec::new)
+                .push(TcpConnection {
+                    local_address: SocketAddr::from((
+                        Ipv4Addr::from(u32::from_be(
+                            tcp_table_entry.dwLocalAddr,
+                        )),
+                        (tcp_table_entry.dwLocalPort as u16).to_be(),
+                    )),
+                    remote_address: SocketAddr::from((
+                        Ipv4Addr::from(u32::from_be(
+                            tcp_table_entry.dwRemoteAddr,
+                        )),
+                        (tcp_table_entry.dwRemotePort as u16).to_be(),
+                    )),
+                    state: tcp_state(tcp_table_entry.dwState),
+                });
+        }
+    }
+    if let Some(tcp6_table_buffer) = get_table(|buffer, size| unsafe {
+        GetTcp6Table2(buffer.cast::<MIB_TCP6TABLE2>(), size, false)
+    }) {
+        let tcp6_table = tcp6_table_buffer.as_ptr().cast::<MIB_TCP6TABLE2>();
+        let num_entries = unsafe { (*tcp6_table).dwNumEntries };
+        for i in 0..num_entries {
+            let i = i as usize;
+            let tcp6_table_entry =
+                unsafe { (*tcp6_table).table.get_unchecked(i) };
+            #[allow(clippy::cast_possible_truncation)]
+            tcp_sockets
+                .entry(tcp6_table_entry.dwOwningPid)
+                .or_insert_with(Vec::new)
+                .push(TcpConnection {
+                    local_address: SocketAddr::V6(SocketAddrV6::new(
+                        Ipv6Addr::from(tcp6_table_entry.LocalAddr),
+                        (tcp6_table_entry.dwLocalPort as u16).to_be(),
+                        0,
+                        tcp6_table_entry.dwLocalScopeId,
+                    )),
+                    remote_address: SocketAddr::V6(SocketAddrV6::new(
+                        Ipv6Addr::from(tcp6_table_entry.RemoteAddr),
+                        (tcp6_table_entry.dwRemotePort as u16).to_be(),
+                        0,
+                        tcp6_table_entry.dwRemoteScopeId,
+                    )),
+                    state: tcp_state(tcp6_table_entry.State),
+                });
+        }
+    }
+    tcp_sockets
+}
+
+fn list_udp_ports_per_process() -> HashMap<u32, HashSet<u16>> {
+    let mut udp_ports = HashMap::new();
+    if let Some(udp_table_buffer) = get_table(|buffer, size| unsafe {
+        GetExtendedUdpTable(
+            buffer.cast::<c_void>(),
+            size,
+            false,
+            AF_INET,
+            UDP_TABLE_OWNER_PID,
+            0,
+        )
+    }) {
+        let udp_table =
+            udp_table_buffer.as_ptr().cast::<MIB_UDPTABLE_OWNER_PID>();
+        let num_entries = unsafe { (*udp_table).dwNumEntries };
+        for i in 0..num_entries {
+            let i = i as usize;
+            let udp_table_entry =
+                unsafe { (*udp_table).table.get_unchecked(i) };
+            #[allow(clippy::cast_possible_truncation)]
+            udp_ports
+                .entry(udp_table_entry.dwOwningPid)
+                .or_insert_with(HashSet::new)
+                .insert((udp_table_entry.dwLocalPort as u16).to_be());
         }
     }
-    tcp_server_ports
+    if let Some(udp6_table_buffer) = get_table(|buffer, size| unsafe {
+        GetExtendedUdpTable(
+            buffer.cast::<c_void>(),
+            size,
+            false,
+            AF_INET6,
+            UDP_TABLE_OWNER_PID,
+            0,
+        )
+    }) {
+        let udp6_table =
+            udp6_table_buffer.as_ptr().cast::<MIB_UDP6TABLE_OWNER_PID>();
+        let num_entries = unsafe { (*udp6_table).dwNumEntries };
+        for i in 0..num_entries {
+            let i = i as usize;
+            let udp6_table_entry =
+                unsafe { (*udp6_table).table.get_unchecked(i) };
+            #[allow(clippy::cast_possible_truncation)]
+            udp_ports
+                .entry(udp6_table_entry.dwOwningPid)
+                .or_insert_with(HashSet::new)
+                .insert((udp6_table_entry.dwLocalPort as u16).to_be());
+        }
+    }
+    udp_ports
+}
+
+fn open_process(
+    id: u32,
+    access: u32,
+) -> SafeHandle {
+    SafeHandle(unsafe { OpenProcess(access, false, id) })
 }
 
-fn open_process(id: u32) -> SafeHandle {
-    SafeHandle(unsafe {
-        OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, id)
-    })
+fn query_start_time(process: HANDLE) -> Option<u64> {
+    let mut creation_time = FILETIME::default();
+    let mut exit_time = FILETIME::default();
+    let mut kernel_time = FILETIME::default();
+    let mut user_time = FILETIME::default();
+    if unsafe {
+        GetProcessTimes(
+            process,
+            std::ptr::addr_of_mut!(creation_time),
+            std::ptr::addr_of_mut!(exit_time),
+            std::ptr::addr_of_mut!(kernel_time),
+            std::ptr::addr_of_mut!(user_time),
+        )
+    } {
+        Some(
+            (u64::from(creation_time.dwHighDateTime) << 32)
+                | u64::from(creation_time.dwLowDateTime),
+        )
+    } else {
+        None
+    }
+}
+
+pub fn start_time_internal(pid: usize) -> Option<u64> {
+    let id = u32::try_from(pid).ok()?;
+    let process = SafeHandle(unsafe {
+        OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, id)
+    });
+    process.ok().and_then(query_start_time)
 }
 
 fn query_full_process_image_name(process: HANDLE) -> Option<PathBuf> {
@@ -248,11 +674,9 @@ fn query_full_process_image_name(process: HANDLE) -> Option<PathBuf> {
             )
         } {
             exe_image_path.truncate(actual_length as usize);
-            break Some(
-                PathBuf::from(OsString::from_wide(&exe_image_path))
-                    .canonicalize()
-                    .unwrap(),
-            );
+            // The image may have been deleted since the process started.
+            let image = PathBuf::from(OsString::from_wide(&exe_image_path));
+            break Some(image.canonicalize().unwrap_or(image));
         }
         if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
             break None;
@@ -261,16 +685,218 @@ fn query_full_process_image_name(process: HANDLE) -> Option<PathBuf> {
     }
 }
 
+struct ProcessEntry {
+    parent_id: u32,
+    name: OsString,
+}
+
+fn list_process_entries() -> HashMap<u32, ProcessEntry> {
+    let mut process_entries = HashMap::new();
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot as isize == INVALID_HANDLE_VALUE {
+        return process_entries;
+    }
+    let snapshot = SafeHandle(snapshot);
+    #[allow(clippy::cast_possible_truncation)]
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        cntUsage: 0,
+        th32ProcessID: 0,
+        th32DefaultHeapID: 0,
+        th32ModuleID: 0,
+        cntThreads: 0,
+        th32ParentProcessID: 0,
+        pcPriClassBase: 0,
+        dwFlags: 0,
+        szExeFile: [0; MAX_PATH],
+    };
+    let mut more =
+        unsafe { Process32FirstW(snapshot.0, std::ptr::addr_of_mut!(entry)) };
+    while more {
+        let name_length =
+            entry.szExeFile.iter().position(|ch| *ch == 0).unwrap_or(MAX_PATH);
+        process_entries.insert(entry.th32ProcessID, ProcessEntry {
+            parent_id: entry.th32ParentProcessID,
+            name: OsString::from_wide(&entry.szExeFile[..name_length]),
+        });
+        more = unsafe {
+            Process32NextW(snapshot.0, std::ptr::addr_of_mut!(entry))
+        };
+    }
+    process_entries
+}
+
+fn query_command_line(process: HANDLE) -> Option<Vec<u16>> {
+    let mut buffer_size = 0;
+    unsafe {
+        NtQueryInformationProcess(
+            process,
+            PROCESS_COMMAND_LINE_INFORMATION,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::addr_of_mut!(buffer_size),
+        );
+    }
+    if buffer_size == 0 {
+        return None;
+    }
+    // The buffer holds a UNICODE_STRING, whose pointer must be aligned.
+    let mut buffer = vec![0_u64; (buffer_size as usize).div_ceil(8)];
+    if unsafe {
+        NtQueryInformationProcess(
+            process,
+            PROCESS_COMMAND_LINE_INFORMATION,
+            buffer.as_mut_ptr().cast::<c_void>(),
+            buffer_size,
+            std::ptr::addr_of_mut!(buffer_size),
+        )
+    } != 0
+    {
+        return None;
+    }
+    let command_line = buffer.as_ptr().cast::<UNICODE_STRING>();
+    let length = usize::from(unsafe { (*command_line).Length }) / 2;
+    let buffer = unsafe { (*command_line).Buffer };
+    if buffer.is_null() {
+        return None;
+    }
+    Some(unsafe { std::slice::from_raw_parts(buffer, length) }.to_vec())
+}
+
+fn query_args(process: HANDLE) -> Vec<OsString> {
+    let command_line = match query_command_line(process) {
+        // CommandLineToArgvW makes up the path of the current program if
+        // given an empty command line.
+        Some(command_line) if !command_line.is_empty() => command_line,
+        _ => return Vec::new(),
+    };
+    let command_line =
+        command_line.into_iter().chain(once(0)).collect::<Vec<_>>();
+    let mut num_args = 0;
+    let arg_pointers = unsafe {
+        CommandLineToArgvW(
+            command_line.as_ptr(),
+            std::ptr::addr_of_mut!(num_args),
+        )
+    };
+    if arg_pointers.is_null() {
+        return Vec::new();
+    }
+    // No argument can be longer than the command line it came from.
+    #[allow(clippy::cast_sign_loss)]
+    let args = (0..num_args as usize)
+        .map(|i| {
+            let arg = unsafe { *arg_pointers.add(i) };
+            let length = (0..command_line.len())
+                .find(|j| unsafe { *arg.add(*j) } == 0)
+                .unwrap_or(0);
+            OsString::from_wide(unsafe {
+                std::slice::from_raw_parts(arg, length)
+            })
+        })
+        .collect();
+    unsafe {
+        LocalFree(arg_pointers.cast::<c_void>());
+    }
+    args
+}
+
+// This holds the system-wide tables from which the information about
+// individual processes is picked out.
+struct ProcessTables {
+    tcp_sockets_per_process: HashMap<u32, Vec<TcpConnection>>,
+    udp_ports_per_process: HashMap<u32, HashSet<u16>>,
+    process_entries: HashMap<u32, ProcessEntry>,
+}
+
+impl ProcessTables {
+    fn read() -> Self {
+        Self {
+            tcp_sockets_per_process: list_tcp_sockets_per_process(),
+            udp_ports_per_process: list_udp_ports_per_process(),
+            process_entries: list_process_entries(),
+        }
+    }
+
+    #[allow(clippy::similar_names)]
+    fn process_info(
+        &mut self,
+        id: u32,
+    ) -> ProcessInfo {
+        let (tcp_listeners, tcp_connections): (Vec<_>, Vec<_>) = self
+            .tcp_sockets_per_process
+            .remove(&id)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|socket| socket.state == TcpState::Listen);
+        let tcp_server_addresses = tcp_listeners
+            .into_iter()
+            .map(|listener| listener.local_address)
+            .collect();
+        let (tcp_server_ports, tcp6_server_ports) =
+            tcp_server_ports(&tcp_server_addresses);
+        // The start time and image need fewer rights than the arguments, so
+        // they are found even for processes which grant no more.
+        let limited_process =
+            open_process(id, PROCESS_QUERY_LIMITED_INFORMATION);
+        let process =
+            open_process(id, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ);
+        let entry = self.process_entries.remove(&id);
+        ProcessInfo {
+            id: id as usize,
+            parent_id: entry
+                .as_ref()
+                .map(|entry| entry.parent_id as usize)
+                .filter(|parent_id| *parent_id != 0),
+            start_time: limited_process.ok().and_then(query_start_time),
+            name: entry.map(|entry| entry.name).unwrap_or_default(),
+            image: limited_process.ok().and_then(query_full_process_image_name),
+            args: process.ok().map(query_args).unwrap_or_default(),
+            tcp_server_addresses,
+            tcp_server_ports,
+            tcp6_server_ports,
+            tcp_connections,
+            udp_ports: self
+                .udp_ports_per_process
+                .remove(&id)
+                .unwrap_or_default(),
+            unix_socket_listeners: HashSet::new(),
+        }
+    }
+}
+
 pub fn list_processes_internal() -> impl Iterator<Item = ProcessInfo> {
-    let mut tcp_server_ports = list_tcp_server_ports_per_process();
-    list_process_ids().into_iter().map(move |id| ProcessInfo {
-        id: id as usize,
-        image: open_process(id)
-            .ok()
-            .and_then(query_full_process_image_name)
-            .unwrap_or_default(),
-        tcp_server_ports: tcp_server_ports.remove(&id).unwrap_or_default(),
-    })
+    let mut process_tables = ProcessTables::read();
+    list_process_ids()
+        .into_iter()
+        .map(move |id| process_tables.process_info(id))
+}
+
+pub fn process_info_internal(pid: usize) -> Option<ProcessInfo> {
+    let id = u32::try_from(pid).ok()?;
+    if list_process_ids().contains(&id) {
+        Some(ProcessTables::read().process_info(id))
+    } else {
+        None
+    }
+}
+
+pub fn process_listening_on_internal(port: u16) -> Vec<ProcessInfo> {
+    // The TCP tables already name the owner of each socket, so only the
+    // owners of matching listeners need to be looked at further.
+    let mut process_tables = ProcessTables::read();
+    let owners = process_tables
+        .tcp_sockets_per_process
+        .iter()
+        .filter(|(_, tcp_sockets)| {
+            tcp_sockets.iter().any(|socket| {
+                socket.state == TcpState::Listen
+                    && socket.local_address.port() == port
+            })
+        })
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    owners.into_iter().map(|id| process_tables.process_info(id)).collect()
 }
 
 fn make_command_line<P, A, S>(
@@ -332,17 +958,50 @@ where
     command_line
 }
 
-pub fn start_detached_internal<P, A, S>(
-    path: P,
-    args: A,
-) -> usize
-where
-    P: AsRef<Path>,
-    A: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
+// The block holds each variable as "name=value", null-terminated, sorted by
+// name without regard to case, and ends with an extra null.
+fn make_environment_block(
+    mut environment: Vec<(OsString, OsString)>
+) -> Vec<u16> {
+    environment.sort_by_key(|(key, _)| key.to_string_lossy().to_uppercase());
+    let mut block = Vec::new();
+    for (key, value) in environment {
+        block.extend(key.encode_wide());
+        block.push(u16::from(b'='));
+        block.extend(value.encode_wide());
+        block.push(0);
+    }
+    // An empty block still needs two nulls.
+    if block.is_empty() {
+        block.push(0);
+    }
+    block.push(0);
+    block
+}
+
+// A null character would cut short the string seen by the operating system,
+// so is reported rather than passed along.
+fn contains_nul<S: AsRef<OsStr>>(string: S) -> bool {
+    string.as_ref().encode_wide().any(|ch| ch == 0)
+}
+
+pub fn start_detached_internal(
+    command: &DetachedCommand
+) -> Result<ProcessHandleInternal, Error> {
+    let environment = command.environment();
+    if contains_nul(&command.program)
+        || command.args.iter().any(contains_nul)
+        || command.current_dir.iter().any(contains_nul)
+        || environment
+            .iter()
+            .flatten()
+            .any(|(key, value)| contains_nul(key) || contains_nul(value))
+    {
+        return Err(Error::InvalidInput);
+    }
+
     // Add file extension because that part is platform-specific.
-    let mut path = Cow::from(path.as_ref());
+    let mut path = Cow::from(command.program.as_path());
     match path.as_ref().extension() {
         Some(extension) if extension == "exe" => {},
         _ => {
@@ -350,29 +1009,57 @@ where
         },
     }
 
-    let command_line = make_command_line(&path, args);
+    let command_line = make_command_line(&path, &command.args);
+    let environment_block = environment.map(make_environment_block);
+    let current_dir = command.current_dir.as_ref().map(|dir| {
+        dir.as_os_str().encode_wide().chain(once(0)).collect::<Vec<_>>()
+    });
+
+    // The files given as standard input and output must be inheritable.  They
+    // are listed as the only handles to inherit, so that the new process does
+    // not also inherit any other inheritable handles of the caller.
+    let stdio = command.open_stdio()?;
+    let handles = [&stdio[0], &stdio[1], &stdio[2]]
+        .map(|file| file.as_raw_handle().cast_const());
+    for handle in handles {
+        let inheritable = unsafe {
+            SetHandleInformation(
+                handle,
+                HANDLE_FLAG_INHERIT,
+                HANDLE_FLAG_INHERIT,
+            )
+        };
+        if !inheritable {
+            return Err(Error::Stdio(io::Error::last_os_error()));
+        }
+    }
+    let mut attributes =
+        AttributeList::inherit_only(&handles).map_err(Error::CreateProcess)?;
 
     // Launch program.
     #[allow(clippy::cast_possible_truncation)]
-    let si = STARTUPINFOW {
-        cb: std::mem::size_of::<STARTUPINFOW>() as u32,
-        lpReserved: std::ptr::null(),
-        lpDesktop: std::ptr::null(),
-        lpTitle: std::ptr::null(),
-        dwX: 0,
-        dwY: 0,
-        dwXSize: 0,
-        dwYSize: 0,
-        dwXCountChars: 0,
-        dwYCountChars: 0,
-        dwFillAttribute: 0,
-        dwFlags: 0,
-        wShowWindow: 0,
-        cbReserved2: 0,
-        lpReserved2: std::ptr::null(),
-        hStdInput: std::ptr::null(),
-        hStdOutput: std::ptr::null(),
-        hStdError: std::ptr::null(),
+    let si = STARTUPINFOEXW {
+        StartupInfo: STARTUPINFOW {
+            cb: std::mem::size_of::<STARTUPINFOEXW>() as u32,
+            lpReserved: std::ptr::null(),
+            lpDesktop: std::ptr::null(),
+            lpTitle: std::ptr::null(),
+            dwX: 0,
+            dwY: 0,
+            dwXSize: 0,
+            dwYSize: 0,
+            dwXCountChars: 0,
+            dwYCountChars: 0,
+            dwFillAttribute: 0,
+            dwFlags: STARTF_USESTDHANDLES,
+            wShowWindow: 0,
+            cbReserved2: 0,
+            lpReserved2: std::ptr::null(),
+            hStdInput: handles[0],
+            hStdOutput: handles[1],
+            hStdError: handles[2],
+        },
+        lpAttributeList: attributes.as_mut_ptr(),
     };
     let mut pi = PROCESS_INFORMATION {
         hProcess: std::ptr::null(),
@@ -388,33 +1075,191 @@ where
             command_line.as_ptr(),
             std::ptr::null(),
             std::ptr::null(),
-            false,
-            DETACHED_PROCESS,
-            std::ptr::null(),
-            std::ptr::null(),
-            &si,
+            true,
+            DETACHED_PROCESS
+                | CREATE_UNICODE_ENVIRONMENT
+                | EXTENDED_STARTUPINFO_PRESENT,
+            environment_block
+                .as_ref()
+                .map_or(std::ptr::null(), |block| block.as_ptr().cast()),
+            current_dir.as_ref().map_or(std::ptr::null(), Vec::as_ptr),
+            std::ptr::addr_of!(si).cast(),
             &mut pi,
         )
     };
     if success {
-        unsafe {
-            CloseHandle(pi.hProcess);
-        }
-        pi.dwProcessId as usize
+        Ok(ProcessHandleInternal {
+            pid: pi.dwProcessId as usize,
+            process: SafeHandle(pi.hProcess),
+        })
     } else {
-        0
+        Err(Error::CreateProcess(io::Error::last_os_error()))
     }
 }
 
-pub fn kill_internal(pid: usize) {
-    #[allow(clippy::cast_possible_truncation)]
+// Windows keeps no zombie processes: an exited process lingers only while
+// something holds a handle to it, and is not reported as running then.
+pub fn is_zombie_internal(_pid: usize) -> bool {
+    false
+}
+
+// A process we may not open is still running.  Otherwise a process which has
+// exited can still be opened while something holds a handle to it, so its
+// exit code tells whether it is still running.
+pub fn is_running_internal(pid: usize) -> bool {
+    let Ok(id) = u32::try_from(pid) else {
+        return false;
+    };
+    let process = SafeHandle(unsafe {
+        OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, id)
+    });
+    match process.ok() {
+        Some(process_handle) => {
+            let mut exit_code = 0;
+            let success = unsafe {
+                GetExitCodeProcess(
+                    process_handle,
+                    std::ptr::addr_of_mut!(exit_code),
+                )
+            };
+            success && exit_code == STILL_ACTIVE
+        },
+        None => {
+            io::Error::last_os_error().raw_os_error()
+                == Some(ERROR_ACCESS_DENIED)
+        },
+    }
+}
+
+// Round up, so as not to give up before the deadline.  INFINITE has special
+// meaning, so stop just short of it.
+fn wait_for_handle(
+    process_handle: HANDLE,
+    timeout: Duration,
+) -> bool {
+    let timeout_ms = u32::try_from(timeout.as_micros().div_ceil(1000))
+        .unwrap_or(INFINITE)
+        .min(INFINITE - 1);
+    let wait_result =
+        unsafe { WaitForSingleObject(process_handle, timeout_ms) };
+    wait_result == WAIT_OBJECT_0
+}
+
+// A process we may not open to wait on could still be polled.
+pub fn wait_for_exit_internal(
+    pid: usize,
+    timeout: Duration,
+) -> bool {
+    let Ok(id) = u32::try_from(pid) else {
+        return true;
+    };
+    let process = SafeHandle(unsafe { OpenProcess(SYNCHRONIZE, false, id) });
+    match process.ok() {
+        Some(process_handle) => wait_for_handle(process_handle, timeout),
+        None => {
+            io::Error::last_os_error().raw_os_error()
+                != Some(ERROR_ACCESS_DENIED)
+                || poll_for_exit(pid, timeout)
+        },
+    }
+}
+
+// Windows has no process groups or sessions to find orphaned members of a
+// process tree, so only the parent/child relationships are used.
+pub fn group_members_internal(
+    _pid: usize,
+    _candidates: &[usize],
+) -> Vec<usize> {
+    Vec::new()
+}
+
+fn kill_error(error: io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(ERROR_ACCESS_DENIED) => Error::PermissionDenied,
+        // OpenProcess gives this error when there is no process with the
+        // given identifier.
+        Some(ERROR_INVALID_PARAMETER) => Error::NoSuchProcess,
+        _ => Error::Kill(error),
+    }
+}
+
+fn open_error(error: io::Error) -> Error {
+    match kill_error(error) {
+        Error::Kill(error) => Error::OpenProcess(error),
+        error => error,
+    }
+}
+
+// Windows has no signals, so the only ones supported are those which would
+// terminate the process anyway.  Terminating a process which has already
+// exited fails as if access were denied, so that is checked for first.
+fn terminate_handle(
+    process_handle: HANDLE,
+    signal: Signal,
+) -> Result<(), Error> {
+    if !matches!(signal, Signal::Kill | Signal::Terminate) {
+        return Err(Error::UnsupportedSignal(signal));
+    }
+    if unsafe { WaitForSingleObject(process_handle, 0) } == WAIT_OBJECT_0 {
+        return Err(Error::NoSuchProcess);
+    }
+    if unsafe { TerminateProcess(process_handle, 255) } {
+        Ok(())
+    } else {
+        Err(kill_error(io::Error::last_os_error()))
+    }
+}
+
+pub fn send_signal_internal(
+    pid: usize,
+    signal: Signal,
+) -> Result<(), Error> {
+    let id = u32::try_from(pid).map_err(|_| Error::NoSuchProcess)?;
+    let process = SafeHandle(unsafe {
+        OpenProcess(PROCESS_TERMINATE | SYNCHRONIZE, false, id)
+    });
     let process_handle =
-        unsafe { OpenProcess(PROCESS_TERMINATE, false, pid as u32) };
-    if process_handle == std::ptr::null() {
-        return;
+        process.ok().ok_or_else(|| kill_error(io::Error::last_os_error()))?;
+    terminate_handle(process_handle, signal)
+}
+
+// A process handle refers to one particular process, and keeps its
+// identifier from being reused while the handle is open.
+pub struct ProcessHandleInternal {
+    pid: usize,
+    process: SafeHandle,
+}
+
+impl ProcessHandleInternal {
+    pub fn open(pid: usize) -> Result<Self, Error> {
+        let id = u32::try_from(pid).map_err(|_| Error::NoSuchProcess)?;
+        let process = SafeHandle(unsafe {
+            OpenProcess(PROCESS_TERMINATE | SYNCHRONIZE, false, id)
+        });
+        if process.ok().is_none() {
+            return Err(open_error(io::Error::last_os_error()));
+        }
+        Ok(Self {
+            pid,
+            process,
+        })
     }
-    unsafe {
-        TerminateProcess(process_handle, 255);
-        CloseHandle(process_handle);
+
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    pub fn send_signal(
+        &self,
+        signal: Signal,
+    ) -> Result<(), Error> {
+        terminate_handle(self.process.0, signal)
+    }
+
+    pub fn wait_for_exit(
+        &self,
+        timeout: Duration,
+    ) -> bool {
+        wait_for_handle(self.process.0, timeout)
     }
 }